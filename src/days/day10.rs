@@ -1,61 +1,202 @@
 use crate::day::Day;
+use crate::util::big::BigUint;
 
-use std::cmp::min;
+use std::collections::BTreeMap;
 use std::iter::once;
 
 pub struct Day10;
 
+// Which joltage differences an adapter accepts, and how far above the highest adapter the device is
+#[derive(Clone, Copy)]
+pub struct Tolerance {
+    min_difference: u64,
+    max_difference: u64,
+    device_offset: u64,
+}
+
+impl Tolerance {
+    pub fn new(min_difference: u64, max_difference: u64, device_offset: u64) -> Tolerance {
+        Tolerance {
+            min_difference,
+            max_difference,
+            device_offset,
+        }
+    }
+
+    fn accepts(&self, from: u64, to: u64) -> bool {
+        let difference = to - from;
+        self.min_difference <= difference && difference <= self.max_difference
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance::new(1, 3, 3)
+    }
+}
+
+// The outlet, every adapter in ascending order, then the device
+fn chain<I>(input: I, tolerance: &Tolerance) -> Vec<u64>
+where
+    I: Iterator<Item = String>,
+{
+    let mut adapters: Vec<u64> = once(0)
+        .chain(input.map(|line| line.parse::<u64>().unwrap()))
+        .collect();
+    adapters.sort();
+    adapters.push(adapters[adapters.len() - 1] + tolerance.device_offset);
+
+    adapters
+}
+
+// How often each difference occurs between consecutive entries of the chain
+fn differences(chain: &[u64]) -> BTreeMap<u64, usize> {
+    let mut histogram = BTreeMap::<u64, usize>::new();
+    for (x, y) in chain.iter().zip(chain.iter().skip(1)) {
+        *histogram.entry(y - x).or_insert(0) += 1;
+    }
+
+    histogram
+}
+
+// Number of ways to get from the outlet to the device, counted for each entry of the chain in
+// turn from the entries within tolerance below it. The count grows exponentially with the number
+// of adapters, so it is kept exact rather than fixed width.
+fn arrangement_count(chain: &[u64], tolerance: &Tolerance) -> BigUint {
+    let mut ways = vec![BigUint::default(); chain.len()];
+    ways[0] = BigUint::from(1);
+
+    for i in 1..chain.len() {
+        ways[i] = (0..i)
+            .rev()
+            .take_while(|&j| chain[i] - chain[j] <= tolerance.max_difference)
+            .filter(|&j| tolerance.accepts(chain[j], chain[i]))
+            .map(|j| &ways[j])
+            .sum();
+    }
+
+    ways.pop().unwrap()
+}
+
+#[allow(dead_code)]
+pub enum Precision {
+    Exact,
+    // Fails rather than wrapping if the count does not fit
+    U64,
+}
+
+pub fn count<I>(input: I, tolerance: Tolerance, precision: Precision) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let count = arrangement_count(&chain(input, &tolerance), &tolerance);
+
+    match precision {
+        Precision::Exact => Ok(count.to_string()),
+        Precision::U64 => count
+            .to_u64()
+            .map(|count| count.to_string())
+            .ok_or_else(|| format!("{} arrangements overflows u64", count)),
+    }
+}
+
+// Lazily walks every valid arrangement from the outlet to the device, depth first. The number of
+// arrangements grows exponentially, so this is only practical for small inputs.
+pub struct Arrangements {
+    chain: Vec<u64>,
+    tolerance: Tolerance,
+    // Indices into the chain of the arrangement so far
+    path: Vec<usize>,
+    // For each entry of `path`, the next index to try after it
+    candidates: Vec<usize>,
+}
+
+impl Iterator for Arrangements {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Vec<u64>> {
+        loop {
+            let current = *self.path.last()?;
+            let candidate = *self.candidates.last()?;
+
+            if current == self.chain.len() - 1 {
+                let arrangement = self.path.iter().map(|&i| self.chain[i]).collect();
+                self.path.pop();
+                self.candidates.pop();
+                return Some(arrangement);
+            }
+
+            if candidate < self.chain.len()
+                && self.chain[candidate] - self.chain[current] <= self.tolerance.max_difference
+            {
+                *self.candidates.last_mut().unwrap() += 1;
+                if self
+                    .tolerance
+                    .accepts(self.chain[current], self.chain[candidate])
+                {
+                    self.path.push(candidate);
+                    self.candidates.push(candidate + 1);
+                }
+            } else {
+                self.path.pop();
+                self.candidates.pop();
+            }
+        }
+    }
+}
+
+pub fn arrangements<I>(input: I, tolerance: Tolerance) -> Arrangements
+where
+    I: Iterator<Item = String>,
+{
+    Arrangements {
+        chain: chain(input, &tolerance),
+        tolerance,
+        path: vec![0],
+        candidates: vec![1],
+    }
+}
+
+pub fn histogram<I>(input: I, tolerance: Tolerance) -> String
+where
+    I: Iterator<Item = String>,
+{
+    let chain = chain(input, &tolerance);
+    let mut lines: Vec<String> = differences(&chain)
+        .iter()
+        .map(|(difference, count)| format!("{}: {}", difference, count))
+        .collect();
+
+    if let Some(i) = (1..chain.len()).find(|&i| !tolerance.accepts(chain[i - 1], chain[i])) {
+        lines.push(format!(
+            "Cannot use every adapter: {} to {} is outside tolerance",
+            chain[i - 1],
+            chain[i]
+        ));
+    }
+
+    lines.join("\n")
+}
+
 impl Day for Day10 {
     type T1 = usize;
     fn part1<I>(input: I) -> usize
     where
         I: Iterator<Item = String>,
     {
-        let mut adapters: Vec<u64> = once(0)
-            .chain(input.map(|line| line.parse::<u64>().unwrap()))
-            .collect();
-        adapters.sort();
-        adapters.push(adapters[adapters.len() - 1] + 3);
-
-        let (ones, threes) =
-            adapters
-                .iter()
-                .zip(adapters.iter().skip(1))
-                .fold((0, 0), |(ones, threes), (x, y)| match y - x {
-                    1 => (ones + 1, threes),
-                    3 => (ones, threes + 1),
-                    _ => (ones, threes),
-                });
-
-        ones * threes
-    }
-
-    type T2 = usize;
-    fn part2<I>(input: I) -> usize
+        let histogram = differences(&chain(input, &Tolerance::default()));
+        let count = |difference| histogram.get(&difference).cloned().unwrap_or(0);
+
+        count(1) * count(3)
+    }
+
+    type T2 = BigUint;
+    fn part2<I>(input: I) -> BigUint
     where
         I: Iterator<Item = String>,
     {
-        let mut adapters: Vec<u64> = once(0)
-            .chain(input.map(|line| line.parse::<u64>().unwrap()))
-            .collect();
-        adapters.sort();
-
-        adapters
-            .iter()
-            .enumerate()
-            .skip(1)
-            .fold(vec![1, 1, 1], |back_trace, (i, adapter)| {
-                let size = min(back_trace.len(), i);
-                let next = back_trace
-                    .iter()
-                    .zip(adapters[i - size..i].iter().cloned().rev())
-                    .filter(|(_, back_adapter)| adapter - back_adapter <= 3)
-                    .map(|(back, _)| back)
-                    .sum();
-
-                once(next)
-                    .chain(back_trace.iter().cloned().take(back_trace.len() - 1))
-                    .collect()
-            })[0]
+        let tolerance = Tolerance::default();
+
+        arrangement_count(&chain(input, &tolerance), &tolerance)
     }
 }
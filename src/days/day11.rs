@@ -1,190 +1,425 @@
 use crate::day::Day;
 
+use std::fs::File;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::thread;
+use std::time::Duration;
+
 pub struct Day11;
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 enum Cell {
     Floor,
     Empty,
     Occupied,
 }
 
-struct Ferry {
-    cells: Vec<Vec<Cell>>,
+// Characters for each kind of cell in a seat layout
+#[derive(Clone, Copy)]
+pub struct Symbols {
+    floor: char,
+    empty: char,
+    occupied: char,
 }
 
-impl Ferry {
-    fn update(&mut self, changes: &Vec<(usize, usize)>) {
-        for &(i, j) in changes {
-            self.flip(i, j);
+impl Symbols {
+    pub fn new(floor: char, empty: char, occupied: char) -> Symbols {
+        Symbols {
+            floor,
+            empty,
+            occupied,
         }
     }
+}
+
+impl Default for Symbols {
+    fn default() -> Symbols {
+        Symbols::new('.', 'L', '#')
+    }
+}
+
+const DIRECTIONS: [(i64, i64); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+// Which cells count as neighbours of a seat
+#[allow(dead_code)]
+pub enum Neighbourhood {
+    // The eight surrounding cells
+    Adjacent,
+    // The first seat in each of the eight directions, looking past floor
+    LineOfSight,
+    // The cells at these (row, column) offsets
+    Offsets(Vec<(i64, i64)>),
+}
+
+// An empty seat becomes occupied when its number of occupied neighbours is within `birth`, and an
+// occupied seat stays occupied while the number is within `survival`. Floor never changes.
+pub struct Rules {
+    neighbourhood: Neighbourhood,
+    birth: RangeInclusive<usize>,
+    survival: RangeInclusive<usize>,
+    symbols: Symbols,
+}
 
-    fn flip(&mut self, row: usize, col: usize) {
-        match &self.cells[row][col] {
-            Cell::Empty => self.cells[row][col] = Cell::Occupied,
-            Cell::Occupied => self.cells[row][col] = Cell::Empty,
-            _ => panic!("Only seats can flip!"),
+impl Rules {
+    pub fn new(
+        neighbourhood: Neighbourhood,
+        birth: RangeInclusive<usize>,
+        survival: RangeInclusive<usize>,
+        symbols: Symbols,
+    ) -> Rules {
+        Rules {
+            neighbourhood,
+            birth,
+            survival,
+            symbols,
         }
     }
 
-    fn get_adjacent_occupied(&self, row: usize, col: usize) -> usize {
-        let dirs = [
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-        ];
+    // Part 1: people leave when four or more adjacent seats are occupied
+    pub fn adjacent() -> Rules {
+        Rules::new(Neighbourhood::Adjacent, 0..=0, 0..=3, Symbols::default())
+    }
 
-        let mut count = 0;
+    // Part 2: people leave when five or more visible seats are occupied
+    pub fn line_of_sight() -> Rules {
+        Rules::new(Neighbourhood::LineOfSight, 0..=0, 0..=4, Symbols::default())
+    }
+}
 
-        let is_valid = |i, j| {
-            if i < 0 || j < 0 {
-                return false;
-            }
+// The seat layout together with each seat's neighbours. Floor never changes, so the neighbours are
+// found once up front and stored as one flat list, with `starts[seat]..starts[seat + 1]` indexing
+// the neighbours of `seat`.
+struct Ferry {
+    cells: Vec<Vec<Cell>>,
+    seats: Vec<(usize, usize)>,
+    occupied: Vec<bool>,
+    starts: Vec<usize>,
+    neighbours: Vec<usize>,
+}
 
-            if i as usize >= self.cells.len() {
-                return false;
-            }
+impl Ferry {
+    fn parse<I>(input: I, rules: &Rules) -> Ferry
+    where
+        I: Iterator<Item = String>,
+    {
+        let symbols = &rules.symbols;
+        let parse_line = |line: String| {
+            line.chars()
+                .map(|c| match c {
+                    c if c == symbols.floor => Cell::Floor,
+                    c if c == symbols.empty => Cell::Empty,
+                    c if c == symbols.occupied => Cell::Occupied,
+                    _ => panic!("Unexpected input: {}", line),
+                })
+                .collect()
+        };
+
+        let cells: Vec<Vec<Cell>> = input.map(parse_line).collect();
 
-            let row = &self.cells[i as usize];
-            if j as usize >= row.len() {
-                return false;
+        let mut seats = Vec::new();
+        let mut occupied = Vec::new();
+        let mut index = Vec::new();
+
+        for (i, row) in cells.iter().enumerate() {
+            index.push(Vec::with_capacity(row.len()));
+
+            for (j, &cell) in row.iter().enumerate() {
+                if cell == Cell::Floor {
+                    index[i].push(None);
+                } else {
+                    index[i].push(Some(seats.len()));
+                    seats.push((i, j));
+                    occupied.push(cell == Cell::Occupied);
+                }
             }
+        }
 
-            return true;
+        let (offsets, past_floor): (&[(i64, i64)], bool) = match &rules.neighbourhood {
+            Neighbourhood::Adjacent => (&DIRECTIONS, false),
+            Neighbourhood::LineOfSight => (&DIRECTIONS, true),
+            Neighbourhood::Offsets(offsets) => (offsets, false),
         };
 
-        for (dx, dy) in dirs.iter() {
-            let i = (row as i64) + dx;
-            let j = (col as i64) + dy;
+        let mut starts = vec![0];
+        let mut neighbours = Vec::new();
 
-            if is_valid(i, j) {
-                match self.cells[i as usize][j as usize] {
-                    Cell::Occupied => {
-                        count += 1;
-                    }
-                    _ => {}
+        for &(row, col) in &seats {
+            for &offset in offsets {
+                if let Some((i, j)) = look(&cells, row, col, offset, past_floor) {
+                    neighbours.extend(index[i][j]);
                 }
             }
+
+            starts.push(neighbours.len());
         }
 
-        count
+        Ferry {
+            cells,
+            seats,
+            occupied,
+            starts,
+            neighbours,
+        }
     }
 
-    fn get_visible_occupied(&self, row: usize, col: usize) -> usize {
-        let dirs = [
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-        ];
+    fn flip(&mut self, seat: usize) {
+        let (row, col) = self.seats[seat];
 
-        let mut count = 0;
+        self.occupied[seat] = !self.occupied[seat];
+        self.cells[row][col] = if self.occupied[seat] {
+            Cell::Occupied
+        } else {
+            Cell::Empty
+        };
+    }
 
-        let is_valid = |i, j| {
-            if i < 0 || j < 0 {
-                return false;
-            }
+    fn occupied_neighbours(&self, seat: usize) -> usize {
+        self.neighbours[self.starts[seat]..self.starts[seat + 1]]
+            .iter()
+            .filter(|&&neighbour| self.occupied[neighbour])
+            .count()
+    }
+
+    // Applies one generation of `rules`, returning the seats that changed
+    fn step(&mut self, rules: &Rules) -> Vec<usize> {
+        let changes: Vec<usize> = (0..self.seats.len())
+            .filter(|&seat| {
+                let count = self.occupied_neighbours(seat);
+
+                if self.occupied[seat] {
+                    !rules.survival.contains(&count)
+                } else {
+                    rules.birth.contains(&count)
+                }
+            })
+            .collect();
+
+        for &seat in &changes {
+            self.flip(seat);
+        }
+
+        changes
+    }
+
+    fn occupied(&self) -> usize {
+        self.occupied.iter().filter(|&&occupied| occupied).count()
+    }
 
-            if i as usize >= self.cells.len() {
-                return false;
+    fn render(&self, symbols: &Symbols) -> String {
+        self.cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        Cell::Floor => symbols.floor,
+                        Cell::Empty => symbols.empty,
+                        Cell::Occupied => symbols.occupied,
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Binary greyscale image with each cell drawn as a `scale` by `scale` square
+    fn to_pgm(&self, scale: usize) -> Vec<u8> {
+        let width = self.cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut image =
+            format!("P5\n{} {}\n255\n", width * scale, self.cells.len() * scale).into_bytes();
+
+        for row in &self.cells {
+            let mut line = Vec::with_capacity(width * scale);
+
+            for j in 0..width {
+                let shade = match row.get(j) {
+                    Some(Cell::Empty) => 160,
+                    Some(Cell::Occupied) => 0,
+                    _ => 255,
+                };
+                line.extend((0..scale).map(|_| shade));
             }
 
-            let row = &self.cells[i as usize];
-            if j as usize >= row.len() {
-                return false;
+            for _ in 0..scale {
+                image.extend(&line);
             }
+        }
 
-            return true;
-        };
+        image
+    }
+}
 
-        for (dx, dy) in dirs.iter() {
-            let mut i = (row as i64) + dx;
-            let mut j = (col as i64) + dy;
-
-            while is_valid(i, j) {
-                match self.cells[i as usize][j as usize] {
-                    Cell::Empty => {
-                        break;
-                    }
-                    Cell::Occupied => {
-                        count += 1;
-                        break;
-                    }
-                    _ => {}
-                }
+// Steps `ferry` until it settles, calling `visit` with the starting layout and then with each new
+// generation and the number of seats that changed. Not every set of rules settles, so a layout that
+// comes round again is reported as an error. Rather than keep every layout, one is saved at each
+// power-of-two generation and the ones after it are compared against it (Brent's method).
+fn simulate<F>(ferry: &mut Ferry, rules: &Rules, mut visit: F) -> Result<(), String>
+where
+    F: FnMut(&Ferry, usize) -> Result<(), String>,
+{
+    let mut saved = ferry.occupied.clone();
+    let mut saved_generation = 0;
 
-                i += dx;
-                j += dy;
-            }
+    visit(ferry, 0)?;
+
+    for generation in 1usize.. {
+        let changes = ferry.step(rules).len();
+
+        if changes == 0 {
+            return Ok(());
+        }
+
+        if ferry.occupied == saved {
+            return Err(format!(
+                "Layout at generation {} repeats generation {} without settling",
+                generation, saved_generation
+            ));
         }
 
-        count
+        visit(ferry, changes)?;
+
+        if generation.is_power_of_two() {
+            saved.clone_from(&ferry.occupied);
+            saved_generation = generation;
+        }
     }
+
+    unreachable!()
 }
 
-fn parse(line: String) -> Vec<Cell> {
-    line.chars()
-        .map(|c| match c {
-            '.' => Cell::Floor,
-            'L' => Cell::Empty,
-            _ => panic!("Unexpected input: {}", line),
-        })
-        .collect()
+// Where `animate` sends each generation
+#[allow(dead_code)]
+pub enum Animation {
+    // Redraws the layout in place, pausing between frames
+    Terminal(Duration),
+    // Every generation one after another in a single text file
+    Text(String),
+    // One PGM image per generation, named <prefix><generation>.pgm, with cells `scale` pixels wide
+    Images { prefix: String, scale: usize },
 }
 
-fn run<I, F>(input: I, threshold: usize, count_adj: F) -> usize
+// Shows how the layout evolves under `rules`, returning the number of seats changed per generation
+pub fn animate<I, W>(
+    input: I,
+    rules: &Rules,
+    animation: &Animation,
+    out: &mut W,
+) -> Result<String, String>
 where
     I: Iterator<Item = String>,
-    F: Fn(&Ferry, usize, usize) -> usize,
+    W: Write,
 {
-    let mut ferry = Ferry {
-        cells: input.map(parse).collect(),
+    let mut ferry = Ferry::parse(input, rules);
+    let mut counts = Vec::<usize>::new();
+    let mut text = match animation {
+        Animation::Text(name) => {
+            Some(File::create(name).map_err(|err| format!("Cannot create {}: {}", name, err))?)
+        }
+        _ => None,
     };
+    let mut write_failed = false;
 
-    loop {
-        let mut changes = Vec::<(usize, usize)>::new();
-
-        for i in 0..ferry.cells.len() {
-            let row = &ferry.cells[i];
-            for j in 0..row.len() {
-                let cell = &row[j];
-                let adj = count_adj(&ferry, i, j);
-                match cell {
-                    Cell::Empty => {
-                        if adj == 0 {
-                            changes.push((i, j));
-                        }
-                    }
-                    Cell::Occupied => {
-                        if adj >= threshold {
-                            changes.push((i, j));
-                        }
-                    }
-                    _ => {}
-                }
+    let result = simulate(&mut ferry, rules, |ferry, changes| {
+        let generation = counts.len();
+        let header = format!("Generation {}: {} changes", generation, changes);
+        counts.push(changes);
+
+        let written = match animation {
+            Animation::Terminal(delay) => {
+                let written = write!(
+                    out,
+                    "\x1b[2J\x1b[H{}\n{}\n",
+                    header,
+                    ferry.render(&rules.symbols)
+                )
+                .and_then(|_| out.flush());
+                thread::sleep(*delay);
+                written
             }
-        }
+            Animation::Text(_) => {
+                let file = text.as_mut().unwrap();
+                writeln!(file, "{}\n{}\n", header, ferry.render(&rules.symbols))
+            }
+            Animation::Images { prefix, scale } => {
+                let name = format!("{}{}.pgm", prefix, generation);
+                File::create(&name).and_then(|mut file| file.write_all(&ferry.to_pgm(*scale)))
+            }
+        };
 
-        if changes.is_empty() {
-            return ferry
-                .cells
-                .iter()
-                .map(|row| row.iter().filter(|cell| **cell == Cell::Occupied).count())
-                .sum();
-        } else {
-            ferry.update(&changes);
+        written.map_err(|err| {
+            write_failed = true;
+            format!("Cannot write generation {}: {}", generation, err)
+        })
+    });
+
+    let mut report: Vec<String> = counts
+        .iter()
+        .enumerate()
+        .map(|(generation, changes)| format!("Generation {}: {} changes", generation, changes))
+        .collect();
+
+    match result {
+        Ok(()) => report.push(format!(
+            "Settled after {} generations with {} occupied seats",
+            counts.len() - 1,
+            ferry.occupied()
+        )),
+        Err(err) if write_failed => return Err(err),
+        Err(err) => report.push(err),
+    }
+
+    Ok(report.join("\n"))
+}
+
+// The position of the first cell from (row, col) in direction (dx, dy), looking past floor if
+// `past_floor`
+fn look(
+    cells: &[Vec<Cell>],
+    row: usize,
+    col: usize,
+    (dx, dy): (i64, i64),
+    past_floor: bool,
+) -> Option<(usize, usize)> {
+    let get = |i: i64, j: i64| {
+        if i < 0 || j < 0 {
+            return None;
         }
+
+        cells
+            .get(i as usize)
+            .and_then(|cells| cells.get(j as usize))
+    };
+
+    let mut i = row as i64 + dx;
+    let mut j = col as i64 + dy;
+
+    while past_floor && get(i, j) == Some(&Cell::Floor) {
+        i += dx;
+        j += dy;
     }
+
+    get(i, j).map(|_| (i as usize, j as usize))
+}
+
+// Occupied seats once the layout stops changing under `rules`
+pub fn run<I>(input: I, rules: &Rules) -> Result<usize, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut ferry = Ferry::parse(input, rules);
+
+    simulate(&mut ferry, rules, |_, _| Ok(()))?;
+
+    Ok(ferry.occupied())
 }
 
 impl Day for Day11 {
@@ -193,7 +428,7 @@ impl Day for Day11 {
     where
         I: Iterator<Item = String>,
     {
-        run(input, 4, |ferry, i, j| ferry.get_adjacent_occupied(i, j))
+        run(input, &Rules::adjacent()).unwrap()
     }
 
     type T2 = usize;
@@ -201,6 +436,6 @@ impl Day for Day11 {
     where
         I: Iterator<Item = String>,
     {
-        run(input, 5, |ferry, i, j| ferry.get_visible_occupied(i, j))
+        run(input, &Rules::line_of_sight()).unwrap()
     }
 }
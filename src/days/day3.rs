@@ -1,7 +1,9 @@
 use crate::day::Day;
 
+use std::ops::Range;
+
 #[derive(Clone)]
-struct Slope {
+pub struct Slope {
     dx: usize,
     dy: usize,
     x: usize,
@@ -10,7 +12,7 @@ struct Slope {
 }
 
 impl Slope {
-    fn new(dx: usize, dy: usize) -> Slope {
+    pub fn new(dx: usize, dy: usize) -> Slope {
         Slope {
             dx,
             dy,
@@ -19,6 +21,25 @@ impl Slope {
             trees: 0,
         }
     }
+
+    // Column visited on the given row of the (unrepeated) map, if the row is visited at all
+    fn column(&self, row: usize) -> Option<usize> {
+        if row.is_multiple_of(self.dy) {
+            Some(row / self.dy * self.dx)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn slopes() -> Vec<Slope> {
+    vec![
+        Slope::new(1, 1),
+        Slope::new(3, 1),
+        Slope::new(5, 1),
+        Slope::new(7, 1),
+        Slope::new(1, 2),
+    ]
 }
 
 fn run<I>(input: I, starts: Vec<Slope>) -> usize
@@ -56,6 +77,61 @@ where
         })
 }
 
+// Draws the forest once per slope, repeated to the right as far as the path goes, marking each
+// visited cell with 'O' (tree) or 'X' (open ground). Only rows within `rows` are drawn, and empty
+// lines are skipped.
+pub fn render<I>(input: I, slopes: Vec<Slope>, rows: Option<Range<usize>>) -> String
+where
+    I: Iterator<Item = String>,
+{
+    let map: Vec<Vec<char>> = input
+        .filter(|line| !line.is_empty())
+        .map(|line| line.chars().collect())
+        .collect();
+    let rows = rows.unwrap_or(0..map.len());
+    let rows = rows.start.min(map.len())..rows.end.min(map.len());
+
+    slopes
+        .iter()
+        .map(|slope| {
+            let trees = map
+                .iter()
+                .enumerate()
+                .filter(|(y, row)| slope.column(*y).is_some_and(|x| row[x % row.len()] == '#'))
+                .count();
+            let width = rows
+                .clone()
+                .filter_map(|y| slope.column(y))
+                .max()
+                .map_or(0, |x| x + 1);
+
+            let mut lines = vec![format!(
+                "Right {}, down {}: {} trees",
+                slope.dx, slope.dy, trees
+            )];
+
+            for y in rows.clone() {
+                let row = &map[y];
+                let repeats = width.div_ceil(row.len()).max(1);
+                let path = slope.column(y);
+
+                lines.push(
+                    (0..repeats * row.len())
+                        .map(|x| match (row[x % row.len()], path == Some(x)) {
+                            ('#', true) => 'O',
+                            (_, true) => 'X',
+                            (c, false) => c,
+                        })
+                        .collect(),
+                );
+            }
+
+            lines.join("\n")
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
 pub struct Day3;
 
 impl Day for Day3 {
@@ -73,15 +149,38 @@ impl Day for Day3 {
     where
         I: Iterator<Item = String>,
     {
-        run(
-            input,
-            vec![
-                Slope::new(1, 1),
-                Slope::new(3, 1),
-                Slope::new(5, 1),
-                Slope::new(7, 1),
-                Slope::new(1, 2),
-            ],
-        )
+        run(input, slopes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Slope};
+
+    const EXAMPLE: &str = "\
+..##.......
+#...#...#..
+.#....#..#.
+..#.#...#.#
+.#...##..#.
+..#.##.....
+.#.#.#....#
+.#........#
+#.##...#...
+#...##....#
+.#..#...#.#
+";
+
+    #[test]
+    fn renders_example_path() {
+        let input = EXAMPLE.split('\n').map(|line| line.to_string());
+        let rendered = render(input, vec![Slope::new(3, 1)], None);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], "Right 3, down 1: 7 trees");
+        assert_eq!(lines[1], "X.##.........##.........##.......");
+        assert_eq!(lines[3], ".#....O..#..#....#..#..#....#..#.");
+        assert_eq!(lines[11], ".#..#...#.#.#..#...#.#.#..#...O.#");
     }
 }
@@ -1,6 +1,11 @@
+extern crate regex;
+
+use self::regex::Regex;
 use crate::day::Day;
+use crate::util::file;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
 
 pub struct Day4;
 
@@ -24,86 +29,435 @@ fn parse_line(line: String) -> Line {
     );
 }
 
-fn is_complete(pairs: &HashMap<String, String>) -> bool {
-    let expected = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+struct Passport {
+    // 1-based line number of the passport's first line in the batch file
+    line: usize,
+    pairs: Vec<(String, String)>,
+}
 
-    expected
-        .iter()
-        .all(|key| pairs.contains_key(&key.to_string()))
-}
-
-fn is_valid(pairs: &HashMap<String, String>) -> bool {
-    let validate =
-        |key, pred: &dyn Fn(&String) -> bool| -> bool { pairs.get(key).map_or(false, pred) };
-
-    let in_range =
-        |min, max| move |s: &String| s.parse::<i32>().map_or(false, |x| min <= x && x <= max);
-
-    // byr (Birth Year) - four digits; at least 1920 and at most 2002.
-    validate("byr", &in_range(1920, 2002)) && 
-    // iyr (Issue Year) - four digits; at least 2010 and at most 2020.
-    validate("iyr", &in_range(2010, 2020)) &&
-    // eyr (Expiration Year) - four digits; at least 2020 and at most 2030.
-    validate("eyr", &in_range(2020, 2030)) &&
-    // hgt (Height) - a number followed by either cm or in:
-    // If cm, the number must be at least 150 and at most 193.
-    // If in, the number must be at least 59 and at most 76.
-    validate("hgt", &|hgt| {
-        if hgt.ends_with("cm") {
-            in_range(150, 193)(&hgt[0..hgt.len()-2].to_string())
-        } else if hgt.ends_with("in") {
-            in_range(59, 76)(&hgt[0..hgt.len()-2].to_string())
-        } else {
-            false
+impl Passport {
+    // Later duplicates overwrite earlier ones
+    fn keys(&self) -> HashMap<String, String> {
+        self.pairs.iter().cloned().collect()
+    }
+
+    fn duplicates(&self) -> Vec<&str> {
+        let mut seen = HashSet::<&str>::new();
+        let mut duplicates = Vec::<&str>::new();
+
+        for (key, _) in &self.pairs {
+            if !seen.insert(key) && !duplicates.contains(&key.as_str()) {
+                duplicates.push(key);
+            }
         }
-    }) &&
-    // hcl (Hair Color) - a # followed by exactly six characters 0-9 or a-f.
-    validate("hcl", &|hcl| {
-        hcl.starts_with("#") && (hcl[1..].chars().all(|c|
-            c.is_numeric() || (c >= 'a' && c <= 'f')))
-    }) &&
-    // ecl (Eye Color) - exactly one of: amb blu brn gry grn hzl oth.
-    validate("ecl", &|ecl| {
-        let expected: HashSet<String> = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].iter().map(|s| s.to_string()).collect();
-        expected.contains(ecl)
-    }) &&
-    // pid (Passport ID) - a nine-digit number, including leading zeroes.
-    validate("pid", &|pid| {
-        pid.len() == 9 && pid.chars().all(|c| c.is_numeric())
-    })
-    // cid (Country ID) - ignored, missing or not.
+
+        duplicates
+    }
 }
 
-fn run<I, F>(input: I, pred: F) -> u64
+fn passports<I>(input: I) -> Vec<Passport>
 where
     I: Iterator<Item = String>,
-    F: Fn(&HashMap<String, String>) -> bool,
 {
-    let lines = input.map(parse_line);
-    let mut keys = HashMap::<String, String>::new();
-    let mut result = 0;
+    let mut result = Vec::<Passport>::new();
+    let mut current: Option<Passport> = None;
 
-    for line in lines {
+    for (i, line) in input.map(parse_line).enumerate() {
         match line {
-            Line::EndOfPassport => {
-                if pred(&keys) {
-                    result += 1;
+            Line::EndOfPassport => result.extend(current.take()),
+            Line::PartialPassport(pairs) => current
+                .get_or_insert_with(|| Passport {
+                    line: i + 1,
+                    pairs: Vec::new(),
+                })
+                .pairs
+                .extend(pairs),
+        }
+    }
+
+    result.extend(current);
+
+    result
+}
+
+// Schema file format, one field per line (blank lines and lines starting with '#' are skipped):
+// <line> := <presence> <space> <key> | <presence> <space> <key> <space> <rule>
+// <presence> := "required" | "optional"
+// <rule> := "range" <space> <int> <space> <int>
+//         | "units" <space> <unit_ranges>
+//         | "pattern" <space> <regex>
+//         | "oneof" <space> <words>
+// <unit_ranges> := <word> <space> <int> <space> <int> | <unit_ranges> <space> <unit_ranges>
+// <regex> := a regular expression without spaces
+// Ranges are inclusive and patterns must match the whole value.
+const DEFAULT_SCHEMA: &str = "\
+required byr range 1920 2002
+required iyr range 2010 2020
+required eyr range 2020 2030
+required hgt units cm 150 193 in 59 76
+required hcl pattern #[0-9a-f]{6}
+required ecl oneof amb blu brn gry grn hzl oth
+required pid pattern [0-9]{9}
+optional cid
+";
+
+const SCHEMA_FILE: &str = "Day4Schema.txt";
+
+enum Rule {
+    Range(i32, i32),
+    Units(Vec<(String, i32, i32)>),
+    Pattern(String, Regex),
+    OneOf(HashSet<String>),
+}
+
+impl Rule {
+    fn parse(kind: &str, args: &[&str]) -> Result<Rule, String> {
+        let int = |s: &str| {
+            s.parse::<i32>()
+                .map_err(|_| format!("Expected an integer, found \"{}\"", s))
+        };
+
+        match kind {
+            "range" => match args {
+                [min, max] => Ok(Rule::Range(int(min)?, int(max)?)),
+                _ => Err("range expects <min> <max>".to_string()),
+            },
+            "units" => {
+                if args.is_empty() || !args.len().is_multiple_of(3) {
+                    return Err("units expects one or more <unit> <min> <max>".to_string());
+                }
+
+                args.chunks(3)
+                    .map(|unit| Ok((unit[0].to_string(), int(unit[1])?, int(unit[2])?)))
+                    .collect::<Result<_, String>>()
+                    .map(Rule::Units)
+            }
+            "pattern" => match args {
+                [pattern] => Regex::new(&format!("^(?:{})$", pattern))
+                    .map(|regex| Rule::Pattern(pattern.to_string(), regex))
+                    .map_err(|err| err.to_string()),
+                _ => Err("pattern expects a single regex without spaces".to_string()),
+            },
+            "oneof" => Ok(Rule::OneOf(args.iter().map(|s| s.to_string()).collect())),
+            _ => Err(format!("Unknown rule: {}", kind)),
+        }
+    }
+
+    fn check(&self, value: &str) -> bool {
+        let in_range = |s: &str, min, max| s.parse::<i32>().is_ok_and(|x| min <= x && x <= max);
+
+        match self {
+            Rule::Range(min, max) => in_range(value, *min, *max),
+            Rule::Units(units) => units.iter().any(|(unit, min, max)| {
+                value
+                    .strip_suffix(unit.as_str())
+                    .is_some_and(|number| in_range(number, *min, *max))
+            }),
+            Rule::Pattern(_, regex) => regex.is_match(value),
+            Rule::OneOf(values) => values.contains(value),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Range(min, max) => write!(f, "range {} {}", min, max),
+            Rule::Units(units) => {
+                write!(f, "units")?;
+                for (unit, min, max) in units {
+                    write!(f, " {} {} {}", unit, min, max)?;
                 }
-                keys.clear();
+                Ok(())
             }
-            Line::PartialPassport(pairs) => {
-                for (key, value) in pairs {
-                    keys.insert(key, value);
+            Rule::Pattern(pattern, _) => write!(f, "pattern {}", pattern),
+            Rule::OneOf(values) => {
+                let mut values: Vec<&String> = values.iter().collect();
+                values.sort();
+                write!(f, "oneof")?;
+                for value in values {
+                    write!(f, " {}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Field {
+    key: String,
+    required: bool,
+    rule: Option<Rule>,
+}
+
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    pub fn parse<I>(lines: I) -> Result<Schema, String>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut fields = Vec::<Field>::new();
+
+        for (i, line) in lines.enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let field = match words.as_slice() {
+                [] => continue,
+                [first, ..] if first.starts_with('#') => continue,
+                [presence, key, rule @ ..] => {
+                    let required = match *presence {
+                        "required" => true,
+                        "optional" => false,
+                        _ => {
+                            return Err(format!(
+                                "Line {}: expected required or optional, found \"{}\"",
+                                i + 1,
+                                presence
+                            ))
+                        }
+                    };
+                    let rule = match rule {
+                        [] => None,
+                        [kind, args @ ..] => Some(
+                            Rule::parse(kind, args)
+                                .map_err(|err| format!("Line {}: {}", i + 1, err))?,
+                        ),
+                    };
+
+                    Field {
+                        key: key.to_string(),
+                        required,
+                        rule,
+                    }
+                }
+                _ => return Err(format!("Line {}: missing field key", i + 1)),
+            };
+
+            fields.push(field);
+        }
+
+        Ok(Schema { fields })
+    }
+
+    // The schema file next to the inputs if there is one, otherwise the puzzle's rules
+    pub fn load() -> Result<Schema, String> {
+        let result = match file::config(SCHEMA_FILE) {
+            Some(lines) => Schema::parse(lines),
+            None => Schema::parse(DEFAULT_SCHEMA.lines().map(|line| line.to_string())),
+        };
+
+        result.map_err(|err| format!("Invalid passport schema: {}", err))
+    }
+
+    // Every reason the passport fails the schema; empty if it is valid
+    fn failures(&self, passport: &Passport) -> Vec<String> {
+        let pairs = passport.keys();
+        let mut failures = Vec::<String>::new();
+
+        for field in &self.fields {
+            match (pairs.get(&field.key), &field.rule) {
+                (None, _) if field.required => failures.push(format!("missing {}", field.key)),
+                (Some(value), Some(rule)) if !rule.check(value) => {
+                    failures.push(format!("rejected {}:{} ({})", field.key, value, rule))
                 }
+                _ => {}
             }
         }
+
+        failures
+    }
+
+    fn is_complete(&self, pairs: &HashMap<String, String>) -> bool {
+        self.fields
+            .iter()
+            .all(|field| !field.required || pairs.contains_key(&field.key))
+    }
+
+    fn is_valid(&self, pairs: &HashMap<String, String>) -> bool {
+        self.is_complete(pairs)
+            && self
+                .fields
+                .iter()
+                .all(|field| match (pairs.get(&field.key), &field.rule) {
+                    (Some(value), Some(rule)) => rule.check(value),
+                    _ => true,
+                })
+    }
+}
+
+fn run<I, F>(input: I, pred: F) -> u64
+where
+    I: Iterator<Item = String>,
+    F: Fn(&HashMap<String, String>) -> bool,
+{
+    passports(input)
+        .iter()
+        .filter(|passport| pred(&passport.keys()))
+        .count() as u64
+}
+
+// One entry per passport listing its fields and why it fails the schema, if it does. Duplicate keys
+// are flagged but, as in the puzzle answers, only the last value counts towards validity.
+pub fn report<I>(input: I, schema: &Schema) -> String
+where
+    I: Iterator<Item = String>,
+{
+    passports(input)
+        .iter()
+        .enumerate()
+        .map(|(i, passport)| {
+            let failures = schema.failures(passport);
+            let status = if failures.is_empty() {
+                "valid"
+            } else {
+                "invalid"
+            };
+            let fields: Vec<String> = passport
+                .pairs
+                .iter()
+                .map(|(key, value)| format!("{}:{}", key, value))
+                .collect();
+
+            let mut lines = vec![
+                format!("Passport {} (line {}): {}", i + 1, passport.line, status),
+                format!("  {}", fields.join(" ")),
+            ];
+            lines.extend(failures.iter().map(|failure| format!("  {}", failure)));
+            lines.extend(
+                passport
+                    .duplicates()
+                    .iter()
+                    .map(|key| format!("  warning: duplicate {}, using the last value", key)),
+            );
+
+            lines.join("\n")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[allow(dead_code)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
+}
 
-    if pred(&keys) {
-        result += 1;
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
     }
+    result.push('"');
+
+    result
+}
+
+// Every parsed passport with one column per schema field, any other fields collected into a
+// final "other" column, and the completeness and validity flags. Duplicate keys keep the last
+// value, as when solving.
+pub fn export<I>(input: I, schema: &Schema, format: ExportFormat) -> String
+where
+    I: Iterator<Item = String>,
+{
+    let known: Vec<&str> = schema
+        .fields
+        .iter()
+        .map(|field| field.key.as_str())
+        .collect();
+
+    let records = passports(input).into_iter().map(|passport| {
+        let pairs = passport.keys();
+        let fields: Vec<Option<&String>> = known.iter().map(|key| pairs.get(*key)).collect();
+        let mut seen = HashSet::<&String>::new();
+        let other: Vec<(&String, &String)> = passport
+            .pairs
+            .iter()
+            .filter(|(key, _)| !known.contains(&key.as_str()) && seen.insert(key))
+            .map(|(key, _)| (key, &pairs[key]))
+            .collect();
+
+        match format {
+            ExportFormat::Csv => {
+                let other: Vec<String> = other
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", key, value))
+                    .collect();
+                let mut columns = vec![passport.line.to_string()];
+                columns.extend(
+                    fields
+                        .iter()
+                        .map(|value| csv_field(value.map_or("", |v| v))),
+                );
+                columns.push(csv_field(&other.join(" ")));
+                columns.push(schema.is_complete(&pairs).to_string());
+                columns.push(schema.is_valid(&pairs).to_string());
 
-    return result;
+                columns.join(",")
+            }
+            ExportFormat::Json => {
+                let object = |entries: Vec<(&str, String)>| {
+                    let entries: Vec<String> = entries
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", json_string(key), value))
+                        .collect();
+                    format!("{{{}}}", entries.join(", "))
+                };
+                let fields = known
+                    .iter()
+                    .zip(fields)
+                    .map(|(key, value)| {
+                        (*key, value.map_or("null".to_string(), |v| json_string(v)))
+                    })
+                    .collect();
+                let other = other
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), json_string(value)))
+                    .collect();
+
+                object(vec![
+                    ("line", passport.line.to_string()),
+                    ("fields", object(fields)),
+                    ("other", object(other)),
+                    ("complete", schema.is_complete(&pairs).to_string()),
+                    ("valid", schema.is_valid(&pairs).to_string()),
+                ])
+            }
+        }
+    });
+
+    match format {
+        ExportFormat::Csv => {
+            let mut header = vec!["line"];
+            header.extend(known.iter());
+            header.extend(["other", "complete", "valid"].iter());
+
+            let mut lines = vec![header.join(",")];
+            lines.extend(records);
+            lines.join("\n")
+        }
+        ExportFormat::Json => {
+            let records: Vec<String> = records.map(|record| format!("  {}", record)).collect();
+            format!("[\n{}\n]", records.join(",\n"))
+        }
+    }
 }
 
 impl Day for Day4 {
@@ -112,7 +466,8 @@ impl Day for Day4 {
     where
         I: Iterator<Item = String>,
     {
-        run(input, is_complete)
+        let schema = Schema::load().unwrap();
+        run(input, |pairs| schema.is_complete(pairs))
     }
 
     type T2 = u64;
@@ -120,6 +475,7 @@ impl Day for Day4 {
     where
         I: Iterator<Item = String>,
     {
-        run(input, is_valid)
+        let schema = Schema::load().unwrap();
+        run(input, |pairs| schema.is_valid(pairs))
     }
 }
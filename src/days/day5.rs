@@ -4,20 +4,211 @@ use std::collections::HashSet;
 
 pub struct Day5;
 
-fn to_seat_id(line: String) -> u64 {
-    let mut id = 0;
-    for c in line.chars() {
-        let value = match c {
-            'F' => 0,
-            'B' => 1,
-            'R' => 1,
-            'L' => 0,
-            _ => panic!("Unexpected character"),
+// Boarding passes are binary space partitioning codes: row bits as F (lower half) or B (upper
+// half), followed by column bits as L (lower half) or R (upper half).
+pub struct Plane {
+    row_bits: u32,
+    column_bits: u32,
+}
+
+impl Plane {
+    // Seat ids and seat counts have to fit in a u64, so a plane has at most 63 bits in total
+    pub fn new(row_bits: u32, column_bits: u32) -> Result<Plane, String> {
+        if row_bits + column_bits > 63 {
+            return Err(format!(
+                "A plane with {} row bits and {} column bits has more seats than fit in a u64",
+                row_bits, column_bits
+            ));
+        }
+
+        Ok(Plane {
+            row_bits,
+            column_bits,
+        })
+    }
+
+    pub fn rows(&self) -> u64 {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> u64 {
+        1 << self.column_bits
+    }
+
+    pub fn seat_id(&self, row: u64, column: u64) -> u64 {
+        (row << self.column_bits) | column
+    }
+
+    pub fn seat(&self, seat_id: u64) -> (u64, u64) {
+        (seat_id >> self.column_bits, seat_id % self.columns())
+    }
+
+    pub fn decode(&self, pass: &str) -> Result<u64, String> {
+        let len = (self.row_bits + self.column_bits) as usize;
+        if pass.chars().count() != len {
+            return Err(format!(
+                "Expected {} characters in boarding pass \"{}\"",
+                len, pass
+            ));
+        }
+
+        let mut id = 0;
+        for (i, c) in pass.chars().enumerate() {
+            let value = match (i < self.row_bits as usize, c) {
+                (true, 'F') | (false, 'L') => 0,
+                (true, 'B') | (false, 'R') => 1,
+                _ => {
+                    return Err(format!(
+                        "Unexpected character '{}' at position {} of boarding pass \"{}\"",
+                        c, i, pass
+                    ))
+                }
+            };
+            id = 2 * id + value;
+        }
+
+        Ok(id)
+    }
+
+    pub fn encode(&self, seat_id: u64) -> Result<String, String> {
+        let (row, column) = self.seat(seat_id);
+        self.encode_seat(row, column)
+    }
+
+    pub fn encode_seat(&self, row: u64, column: u64) -> Result<String, String> {
+        if row >= self.rows() || column >= self.columns() {
+            return Err(format!(
+                "Seat at row {}, column {} is outside a {}x{} plane",
+                row,
+                column,
+                self.rows(),
+                self.columns()
+            ));
+        }
+
+        let bits = |value: u64, bits: u32, zero, one| {
+            (0..bits)
+                .rev()
+                .map(move |bit| if value >> bit & 1 == 0 { zero } else { one })
         };
-        id = 2 * id + value;
+
+        Ok(bits(row, self.row_bits, 'F', 'B')
+            .chain(bits(column, self.column_bits, 'L', 'R'))
+            .collect())
+    }
+}
+
+impl Default for Plane {
+    fn default() -> Plane {
+        Plane::new(7, 3).unwrap()
+    }
+}
+
+fn to_seat_id(line: String) -> Result<u64, String> {
+    Plane::default().decode(&line)
+}
+
+fn occupied<I>(input: I) -> Result<HashSet<u64>, String>
+where
+    I: Iterator<Item = String>,
+{
+    input
+        .enumerate()
+        .map(|(i, line)| to_seat_id(line).map_err(|err| format!("Line {}: {}", i + 1, err)))
+        .collect()
+}
+
+// One line per row, '#' for an occupied seat and '.' for a free one
+pub fn seat_map<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let plane = Plane::default();
+    let seat_ids = occupied(input)?;
+    let width = (plane.rows() - 1).to_string().len();
+
+    Ok((0..plane.rows())
+        .map(|row| {
+            let seats: String = (0..plane.columns())
+                .map(|column| {
+                    if seat_ids.contains(&plane.seat_id(row, column)) {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:>width$} {}", row, seats, width = width)
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+// Rows missing entirely at the front and back of the plane, every other free seat, and all runs
+// of consecutive free seat ids
+pub fn gaps<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let plane = Plane::default();
+    let seat_ids = occupied(input)?;
+    let is_empty_row = |row: &u64| {
+        (0..plane.columns()).all(|column| !seat_ids.contains(&plane.seat_id(*row, column)))
+    };
+    let describe_rows = |rows: &[u64]| match rows {
+        [] => "none".to_string(),
+        [row] => row.to_string(),
+        [first, .., last] => format!("{}-{}", first, last),
+    };
+
+    let front: Vec<u64> = (0..plane.rows()).take_while(is_empty_row).collect();
+    let back: Vec<u64> = (front.len() as u64..plane.rows())
+        .rev()
+        .take_while(is_empty_row)
+        .collect::<Vec<u64>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let middle = front.len() as u64..plane.rows() - back.len() as u64;
+
+    let free: Vec<String> = middle
+        .flat_map(|row| (0..plane.columns()).map(move |column| (row, column)))
+        .filter(|(row, column)| !seat_ids.contains(&plane.seat_id(*row, *column)))
+        .map(|(row, column)| {
+            format!(
+                "{} (row {}, column {})",
+                plane.seat_id(row, column),
+                row,
+                column
+            )
+        })
+        .collect();
+
+    let mut blocks = Vec::<(u64, u64)>::new();
+    for seat_id in (0..plane.rows() * plane.columns()).filter(|id| !seat_ids.contains(id)) {
+        match blocks.last_mut() {
+            Some((_, end)) if *end + 1 == seat_id => *end = seat_id,
+            _ => blocks.push((seat_id, seat_id)),
+        }
     }
+    let blocks: Vec<String> = blocks
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{} ({} seats)", start, end, end - start + 1)
+            }
+        })
+        .collect();
 
-    id
+    Ok([
+        format!("Missing rows at front: {}", describe_rows(&front)),
+        format!("Missing rows at back: {}", describe_rows(&back)),
+        format!("Free seats: {}", free.join(", ")),
+        format!("Free blocks: {}", blocks.join(", ")),
+    ]
+    .join("\n"))
 }
 
 impl Day for Day5 {
@@ -26,7 +217,7 @@ impl Day for Day5 {
     where
         I: Iterator<Item = String>,
     {
-        input.map(to_seat_id).max().unwrap()
+        occupied(input).unwrap().into_iter().max().unwrap()
     }
 
     type T2 = u64;
@@ -34,7 +225,7 @@ impl Day for Day5 {
     where
         I: Iterator<Item = String>,
     {
-        let seat_ids: HashSet<u64> = input.map(to_seat_id).collect();
+        let seat_ids = occupied(input).unwrap();
 
         // Infinite range but we just want the first one satisfying these conditions
         (1..)
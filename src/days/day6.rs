@@ -1,33 +1,172 @@
 use crate::day::Day;
 
+use std::collections::HashMap;
 use std::collections::HashSet;
-use std::iter::once;
+use std::iter::FromIterator;
 
 pub struct Day6;
 
-impl Day for Day6 {
-    type T1 = usize;
-    fn part1<I>(input: I) -> usize
+// Which questions count for a group, based on how many of its members answered them
+#[allow(dead_code)]
+pub enum Aggregate {
+    Union,
+    Intersection,
+    AtLeast(usize),
+    ExactlyOne,
+}
+
+// Answers for a-z are kept as bits, anything else falls back to a general set
+#[derive(Clone, Default)]
+struct AnswerSet {
+    letters: u32,
+    other: HashSet<char>,
+}
+
+impl AnswerSet {
+    fn insert(&mut self, c: char) {
+        if c.is_ascii_lowercase() {
+            self.letters |= 1 << (c as u32 - 'a' as u32);
+        } else {
+            self.other.insert(c);
+        }
+    }
+
+    fn union_with(&mut self, other: &AnswerSet) {
+        self.letters |= other.letters;
+        self.other.extend(other.other.iter().cloned());
+    }
+
+    fn intersect_with(&mut self, other: &AnswerSet) {
+        self.letters &= other.letters;
+        self.other.retain(|c| other.other.contains(c));
+    }
+
+    fn len(&self) -> usize {
+        self.letters.count_ones() as usize + self.other.len()
+    }
+}
+
+impl FromIterator<char> for AnswerSet {
+    fn from_iter<I>(iter: I) -> AnswerSet
     where
-        I: Iterator<Item = String>,
+        I: IntoIterator<Item = char>,
+    {
+        let mut set = AnswerSet::default();
+        for c in iter {
+            set.insert(c);
+        }
+
+        set
+    }
+}
+
+struct Group {
+    members: Vec<AnswerSet>,
+}
+
+impl Group {
+    // Questions answered by a number of members satisfying `pred`
+    fn counted<F>(&self, pred: F) -> AnswerSet
+    where
+        F: Fn(usize) -> bool,
     {
-        let mut qs = HashSet::<char>::new();
-        let mut result = 0;
+        let mut letters = [0; 26];
+        let mut other = HashMap::<char, usize>::new();
 
-        for line in input {
-            if line.len() == 0 {
-                result += qs.len();
-                qs = HashSet::<char>::new();
+        for member in &self.members {
+            for (i, count) in letters.iter_mut().enumerate() {
+                *count += (member.letters >> i & 1) as usize;
+            }
+            for &c in &member.other {
+                *other.entry(c).or_insert(0) += 1;
             }
+        }
 
-            for c in line.chars() {
-                qs.insert(c);
+        AnswerSet {
+            letters: letters
+                .iter()
+                .enumerate()
+                .filter(|&(_, &count)| count > 0 && pred(count))
+                .fold(0, |bits, (i, _)| bits | 1 << i),
+            other: other
+                .into_iter()
+                .filter(|&(_, count)| pred(count))
+                .map(|(c, _)| c)
+                .collect(),
+        }
+    }
+
+    fn answers(&self, aggregate: &Aggregate) -> AnswerSet {
+        match aggregate {
+            Aggregate::Union => {
+                self.members
+                    .iter()
+                    .fold(AnswerSet::default(), |mut answers, member| {
+                        answers.union_with(member);
+                        answers
+                    })
+            }
+            Aggregate::Intersection => {
+                let mut members = self.members.iter();
+                let first = members.next().cloned().unwrap_or_default();
+                members.fold(first, |mut answers, member| {
+                    answers.intersect_with(member);
+                    answers
+                })
+            }
+            Aggregate::AtLeast(k) => self.counted(|count| count >= *k),
+            Aggregate::ExactlyOne => self.counted(|count| count == 1),
+        }
+    }
+}
+
+// Groups are separated by blank lines, one member per line
+struct Groups<I> {
+    lines: I,
+}
+
+impl<I> Iterator for Groups<I>
+where
+    I: Iterator<Item = String>,
+{
+    type Item = Group;
+
+    fn next(&mut self) -> Option<Group> {
+        let mut members = Vec::<AnswerSet>::new();
+
+        for line in self.lines.by_ref() {
+            if !line.is_empty() {
+                members.push(line.chars().collect());
+            } else if !members.is_empty() {
+                break;
             }
         }
 
-        result += qs.len();
+        if members.is_empty() {
+            None
+        } else {
+            Some(Group { members })
+        }
+    }
+}
+
+// Sum over all groups of the number of questions counted by `aggregate`
+pub fn count<I>(input: I, aggregate: Aggregate) -> usize
+where
+    I: Iterator<Item = String>,
+{
+    Groups { lines: input }
+        .map(|group| group.answers(&aggregate).len())
+        .sum()
+}
 
-        result
+impl Day for Day6 {
+    type T1 = usize;
+    fn part1<I>(input: I) -> usize
+    where
+        I: Iterator<Item = String>,
+    {
+        count(input, Aggregate::Union)
     }
 
     type T2 = usize;
@@ -35,26 +174,6 @@ impl Day for Day6 {
     where
         I: Iterator<Item = String>,
     {
-        // 'a'..'z' doesn't include 'z' :(
-        let init: HashSet<char> = ('a'..'z').chain(once('z')).collect();
-
-        let mut qs = init.clone();
-        let mut result = 0;
-
-        for line in input {
-            if line.len() == 0 {
-                result += qs.len();
-                qs = init.clone();
-            } else {
-                qs = qs
-                    .intersection(&(line.chars().collect()))
-                    .cloned()
-                    .collect();
-            }
-        }
-
-        result += qs.len();
-
-        result
+        count(input, Aggregate::Intersection)
     }
 }
@@ -1,7 +1,17 @@
 use crate::day::Day;
+extern crate nom;
 
+use self::nom::branch::alt;
+use self::nom::bytes::complete::{tag, take_while1};
+use self::nom::character::complete::digit1;
+use self::nom::combinator::{all_consuming, cut, map_res, verify};
+use self::nom::multi::many0;
+use self::nom::sequence::preceded;
+use self::nom::IResult;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
+use std::iter::once;
 
 pub struct Day7;
 
@@ -24,6 +34,12 @@ struct Bag {
     color: String,
 }
 
+impl fmt::Display for Bag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.descriptor, self.color)
+    }
+}
+
 fn bag(descriptor: &str, color: &str) -> Bag {
     Bag {
         descriptor: descriptor.to_string(),
@@ -41,63 +57,368 @@ struct Rule {
     contains: Vec<BagQuantity>,
 }
 
-fn parse_rule(line: String) -> Rule {
-    let skip_words: HashSet<&str> = [
-        "bags", "bag", "bags.", "bag.", "bags,", "bag,", "contain", "no", "other",
-    ]
-    .iter()
-    .cloned()
-    .collect();
-    let mut bag_words = line
-        .split_whitespace()
-        .filter(|word| !skip_words.contains(word));
-    let container_descriptor = bag_words.next().unwrap();
-    let container_color = bag_words.next().unwrap();
-
-    let mut contained = Vec::<BagQuantity>::new();
-    while let Some(contained_count) = bag_words.next() {
-        let contained_descriptor = bag_words.next().unwrap();
-        let contained_color = bag_words.next().unwrap();
-
-        contained.push(BagQuantity {
-            bag: bag(contained_descriptor, contained_color),
-            count: contained_count.parse::<usize>().unwrap(),
+fn word(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_ascii_lowercase())(input)
+}
+
+fn bag_name(input: &str) -> IResult<&str, Bag> {
+    let (input, descriptor) = word(input)?;
+    let (input, _) = tag(" ")(input)?;
+    let (input, color) = word(input)?;
+
+    Ok((input, bag(descriptor, color)))
+}
+
+fn plural_bag(input: &str) -> IResult<&str, Bag> {
+    let (input, bag) = bag_name(input)?;
+    let (input, _) = tag(" bags")(input)?;
+
+    Ok((input, bag))
+}
+
+fn single_bag(input: &str) -> IResult<&str, Bag> {
+    let (input, bag) = bag_name(input)?;
+    let (input, _) = tag(" bag")(input)?;
+
+    Ok((input, bag))
+}
+
+fn bag_quantity(input: &str) -> IResult<&str, BagQuantity> {
+    let (input, count) = verify(map_res(digit1, |s: &str| s.parse::<usize>()), |count| {
+        *count > 0
+    })(input)?;
+    // Once there is a count the rest of the quantity has to follow
+    let (input, _) = cut(tag(" "))(input)?;
+    let (input, bag) = if count == 1 {
+        cut(single_bag)(input)?
+    } else {
+        cut(plural_bag)(input)?
+    };
+
+    Ok((input, BagQuantity { bag, count }))
+}
+
+fn bag_quantities(input: &str) -> IResult<&str, Vec<BagQuantity>> {
+    let (input, first) = bag_quantity(input)?;
+    let (input, rest) = many0(preceded(tag(", "), cut(bag_quantity)))(input)?;
+
+    Ok((input, once(first).chain(rest).collect()))
+}
+
+fn no_other_bags(input: &str) -> IResult<&str, Vec<BagQuantity>> {
+    let (input, _) = tag("no other bags")(input)?;
+
+    Ok((input, Vec::new()))
+}
+
+fn rule(input: &str) -> IResult<&str, Rule> {
+    let (input, container) = plural_bag(input)?;
+    let (input, _) = tag(" contain ")(input)?;
+    let (input, contains) = alt((bag_quantities, no_other_bags))(input)?;
+    let (input, _) = tag(".")(input)?;
+
+    Ok((
+        input,
+        Rule {
+            container,
+            contains,
+        },
+    ))
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    match all_consuming(rule)(line) {
+        Ok((_, rule)) => Ok(rule),
+        Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+            let column = line.len() - err.input.len() + 1;
+            if err.input.is_empty() {
+                Err(format!("Unexpected end of rule at column {}", column))
+            } else {
+                Err(format!("Unexpected \"{}\" at column {}", err.input, column))
+            }
+        }
+        Err(nom::Err::Incomplete(_)) => Err("Incomplete rule".to_string()),
+    }
+}
+
+// The rules in both directions: what each bag contains, and what each bag is contained in
+pub struct Rules {
+    contains: HashMap<Bag, Vec<BagQuantity>>,
+    contained_in: HashMap<Bag, Vec<Bag>>,
+}
+
+impl Rules {
+    fn parse<I>(input: I) -> Result<Rules, String>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut contains = HashMap::<Bag, Vec<BagQuantity>>::new();
+        let mut contained_in = HashMap::<Bag, Vec<Bag>>::new();
+
+        for (i, line) in input.enumerate() {
+            let rule = parse_rule(&line).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+            for quantity in &rule.contains {
+                contained_in
+                    .entry(quantity.bag.clone())
+                    .or_default()
+                    .push(rule.container.clone());
+            }
+            contains.insert(rule.container, rule.contains);
+        }
+
+        Ok(Rules {
+            contains,
+            contained_in,
         })
     }
 
-    Rule {
-        container: bag(container_descriptor, container_color),
-        contains: contained,
+    // Every bag that can eventually contain `bag`
+    fn containers(&self, bag: &Bag) -> HashSet<Bag> {
+        let mut result = HashSet::<Bag>::new();
+        let mut pending = vec![bag];
+
+        while let Some(inner_bag) = pending.pop() {
+            for outer_bag in self.contained_in.get(inner_bag).into_iter().flatten() {
+                if result.insert(outer_bag.clone()) {
+                    pending.push(outer_bag);
+                }
+            }
+        }
+
+        result
     }
-}
 
-fn bag_contained(graph: &HashMap<Bag, Vec<Bag>>, bag: &Bag) -> HashSet<Bag> {
-    match graph.get(&bag) {
-        Some(outer_bags) => {
-            let mut result = HashSet::<Bag>::new();
+    // Every bag that can eventually be inside `bag`
+    fn contents(&self, bag: &Bag) -> HashSet<Bag> {
+        let mut result = HashSet::<Bag>::new();
+        let mut pending = vec![bag];
 
-            for outer_bag in outer_bags {
-                result.insert(outer_bag.clone());
-                result = result
-                    .union(&bag_contained(&graph, &outer_bag))
-                    .cloned()
-                    .collect();
+        while let Some(outer_bag) = pending.pop() {
+            for quantity in self.contains.get(outer_bag).into_iter().flatten() {
+                if result.insert(quantity.bag.clone()) {
+                    pending.push(&quantity.bag);
+                }
             }
+        }
 
-            result
+        result
+    }
+
+    fn rule(&self, bag: &Bag, container: Option<&Bag>) -> Result<&Vec<BagQuantity>, String> {
+        self.contains.get(bag).ok_or_else(|| match container {
+            Some(container) => format!("No rule for {} bags, contained in {} bags", bag, container),
+            None => format!("No rule for {} bags", bag),
+        })
+    }
+
+    // Folds the contents of `bag` bottom up, evaluating each distinct bag once. `combine` is given
+    // the count and value of each bag directly inside. `path` is the chain of bags being evaluated,
+    // used to report cycles.
+    fn evaluate<F>(
+        &self,
+        bag: &Bag,
+        combine: &F,
+        memo: &mut HashMap<Bag, usize>,
+        path: &mut Vec<Bag>,
+    ) -> Result<usize, String>
+    where
+        F: Fn(Vec<(usize, usize)>) -> usize,
+    {
+        if let Some(&value) = memo.get(bag) {
+            return Ok(value);
         }
-        None => HashSet::<Bag>::new(),
+
+        if let Some(start) = path.iter().position(|outer_bag| outer_bag == bag) {
+            let cycle: Vec<String> = path[start..]
+                .iter()
+                .chain(once(bag))
+                .map(|bag| bag.to_string())
+                .collect();
+            return Err(format!("Bags contain themselves: {}", cycle.join(" -> ")));
+        }
+
+        let contents = self.rule(bag, path.last())?;
+
+        path.push(bag.clone());
+        let mut values = Vec::<(usize, usize)>::new();
+        for quantity in contents {
+            values.push((
+                quantity.count,
+                self.evaluate(&quantity.bag, combine, memo, path)?,
+            ));
+        }
+        path.pop();
+
+        let value = combine(values);
+        memo.insert(bag.clone(), value);
+
+        Ok(value)
+    }
+
+    // Total number of bags inside `bag`
+    fn count(&self, bag: &Bag) -> Result<usize, String> {
+        let combine = |values: Vec<(usize, usize)>| {
+            values
+                .iter()
+                .map(|(count, inside)| count * (1 + inside))
+                .sum()
+        };
+
+        self.evaluate(bag, &combine, &mut HashMap::new(), &mut Vec::new())
+    }
+
+    // Levels of bags nested inside `bag`; 0 if it is empty
+    fn depth(&self, bag: &Bag) -> Result<usize, String> {
+        let combine = |values: Vec<(usize, usize)>| {
+            values.iter().map(|(_, depth)| 1 + depth).max().unwrap_or(0)
+        };
+
+        self.evaluate(bag, &combine, &mut HashMap::new(), &mut Vec::new())
+    }
+
+    // Everything inside `bag`, one line per contained bag indented by nesting level
+    fn tree(&self, bag: &Bag) -> Result<String, String> {
+        // Rejects cycles and missing rules before expanding
+        self.depth(bag)?;
+
+        let mut lines = vec![bag.to_string()];
+        self.tree_lines(bag, 1, &mut lines);
+
+        Ok(lines.join("\n"))
+    }
+
+    fn tree_lines(&self, bag: &Bag, depth: usize, lines: &mut Vec<String>) {
+        for quantity in &self.contains[bag] {
+            lines.push(format!(
+                "{}{} {}",
+                "  ".repeat(depth),
+                quantity.count,
+                quantity.bag
+            ));
+            self.tree_lines(&quantity.bag, depth + 1, lines);
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub enum Query {
+    // Which bags can eventually contain the bag
+    Containers,
+    // How many bags the bag contains
+    Count,
+    // How deeply bags are nested inside the bag
+    Depth,
+    // The full containment tree of the bag
+    Tree,
+}
+
+fn parse_bag(name: &str) -> Result<Bag, String> {
+    match name.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        [descriptor, color] => Ok(bag(descriptor, color)),
+        _ => Err(format!(
+            "Expected \"<descriptor> <color>\", found \"{}\"",
+            name
+        )),
     }
 }
 
-fn bag_count(graph: &HashMap<Bag, Vec<BagQuantity>>, bag: &Bag) -> usize {
-    let mut sum = 0;
+// Answers `query` for a bag given as "<descriptor> <color>", e.g. "shiny gold"
+pub fn query<I>(input: I, query: Query, name: &str) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let needle = parse_bag(name)?;
+    let rules = Rules::parse(input)?;
 
-    for quantity in graph.get(bag).unwrap() {
-        sum += quantity.count * (1 + bag_count(&graph, &quantity.bag));
+    if !rules.contains.contains_key(&needle) {
+        return Err(format!("No rule for {} bags", needle));
     }
 
-    sum
+    match query {
+        Query::Containers => {
+            let mut containers: Vec<String> = rules
+                .containers(&needle)
+                .iter()
+                .map(|bag| bag.to_string())
+                .collect();
+            containers.sort();
+            Ok(containers.join("\n"))
+        }
+        Query::Count => rules.count(&needle).map(|count| count.to_string()),
+        Query::Depth => rules.depth(&needle).map(|depth| depth.to_string()),
+        Query::Tree => rules.tree(&needle),
+    }
+}
+
+#[allow(dead_code)]
+pub enum DotScope {
+    All,
+    // The bag and everything that can be inside it
+    From(String),
+    // The bag and everything that can contain it
+    To(String),
+}
+
+// The rules as a Graphviz digraph with an edge from each bag to the bags directly inside it,
+// labelled with how many
+pub fn to_dot<I>(input: I, scope: DotScope) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let rules = Rules::parse(input)?;
+    let all = || -> HashSet<Bag> {
+        rules
+            .contains
+            .keys()
+            .chain(rules.contained_in.keys())
+            .cloned()
+            .collect()
+    };
+    let bags = match scope {
+        DotScope::All => all(),
+        DotScope::From(name) => {
+            let needle = parse_bag(&name)?;
+            let mut bags = rules.contents(&needle);
+            bags.insert(needle);
+            bags
+        }
+        DotScope::To(name) => {
+            let needle = parse_bag(&name)?;
+            let mut bags = rules.containers(&needle);
+            bags.insert(needle);
+            bags
+        }
+    };
+
+    let mut nodes: Vec<String> = bags.iter().map(|bag| format!("    \"{}\";", bag)).collect();
+    nodes.sort();
+
+    let mut edges: Vec<String> = bags
+        .iter()
+        .flat_map(|bag| {
+            rules
+                .contains
+                .get(bag)
+                .into_iter()
+                .flatten()
+                .map(move |quantity| (bag, quantity))
+        })
+        .filter(|(_, quantity)| bags.contains(&quantity.bag))
+        .map(|(bag, quantity)| {
+            format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                bag, quantity.bag, quantity.count
+            )
+        })
+        .collect();
+    edges.sort();
+
+    let mut lines = vec!["digraph bags {".to_string()];
+    lines.extend(nodes);
+    lines.extend(edges);
+    lines.push("}".to_string());
+
+    Ok(lines.join("\n"))
 }
 
 impl Day for Day7 {
@@ -106,21 +427,10 @@ impl Day for Day7 {
     where
         I: Iterator<Item = String>,
     {
-        let rules = input.map(parse_rule);
-        let needle = bag("shiny", "gold");
-        let mut graph = HashMap::<Bag, Vec<Bag>>::new();
-
-        for rule in rules {
-            for quantity in rule.contains {
-                if let Some(contains) = graph.get_mut(&quantity.bag) {
-                    contains.push(rule.container.clone());
-                } else {
-                    graph.insert(quantity.bag, vec![rule.container.clone()]);
-                }
-            }
-        }
-
-        bag_contained(&graph, &needle).len()
+        Rules::parse(input)
+            .unwrap()
+            .containers(&bag("shiny", "gold"))
+            .len()
     }
 
     type T2 = usize;
@@ -128,14 +438,9 @@ impl Day for Day7 {
     where
         I: Iterator<Item = String>,
     {
-        let rules = input.map(parse_rule);
-        let needle = bag("shiny", "gold");
-        let mut graph = HashMap::<Bag, Vec<BagQuantity>>::new();
-
-        for rule in rules {
-            graph.insert(rule.container, rule.contains);
-        }
-
-        bag_count(&graph, &needle)
+        Rules::parse(input)
+            .unwrap()
+            .count(&bag("shiny", "gold"))
+            .unwrap()
     }
 }
@@ -1,104 +1,1251 @@
 use crate::day::Day;
 
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, Write};
 
 pub struct Day8;
 
-enum Op {
-    Nop(i64),
-    Acc(i64),
-    Jmp(i64),
+// Registers of the console, written by name in the source. The first is the accumulator. Values
+// wrap around on overflow.
+const REGISTERS: [char; 2] = ['a', 'b'];
+
+// A register can keep changing without the program ever repeating a state, so running gives up
+// after this many steps
+const STEP_LIMIT: usize = 1_000_000;
+
+// Most operands any instruction takes
+const MAX_OPERANDS: usize = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operand {
+    // A signed constant, e.g. "+3"
+    Value,
+    // A jump relative to the instruction, written as a signed offset or as a label
+    Offset,
+    // One of `REGISTERS`, stored as its index
+    Register,
+}
+
+// Where control goes after an instruction
+#[derive(Clone, Copy, PartialEq)]
+enum Flow {
+    // The following instruction
+    Next,
+    // Relative to the instruction by its offset operand
+    Jump,
+    // Past the end of the program
+    Halt,
+}
+
+// Everything about an opcode: how it is written, where control can go after it, and what it does
+struct OpDefinition {
+    name: &'static str,
+    operands: &'static [Operand],
+    // Every flow `execute` can return, so control flow can be analysed without running anything
+    flows: &'static [Flow],
+    // Runs the instruction given its operand values, the registers and the output
+    execute: fn(&[i64], &mut [i64], &mut Vec<i64>) -> Flow,
+    // The opcode a repair may swap this one for, with the same operands
+    flipped: Option<&'static str>,
+}
+
+// The instruction set. A new opcode only needs an entry here.
+const INSTRUCTION_SET: &[OpDefinition] = &[
+    OpDefinition {
+        name: "nop",
+        operands: &[Operand::Value],
+        flows: &[Flow::Next],
+        execute: |_, _, _| Flow::Next,
+        flipped: Some("jmp"),
+    },
+    OpDefinition {
+        name: "acc",
+        operands: &[Operand::Value],
+        flows: &[Flow::Next],
+        execute: |args, registers, _| {
+            registers[0] = registers[0].wrapping_add(args[0]);
+            Flow::Next
+        },
+        flipped: None,
+    },
+    OpDefinition {
+        name: "jmp",
+        operands: &[Operand::Offset],
+        flows: &[Flow::Jump],
+        execute: |_, _, _| Flow::Jump,
+        flipped: Some("nop"),
+    },
+    // Adds a value to a register
+    OpDefinition {
+        name: "add",
+        operands: &[Operand::Register, Operand::Value],
+        flows: &[Flow::Next],
+        execute: |args, registers, _| {
+            let register = &mut registers[args[0] as usize];
+            *register = register.wrapping_add(args[1]);
+            Flow::Next
+        },
+        flipped: None,
+    },
+    // Jumps unless the register is zero
+    OpDefinition {
+        name: "jnz",
+        operands: &[Operand::Register, Operand::Offset],
+        flows: &[Flow::Next, Flow::Jump],
+        execute: |args, registers, _| {
+            if registers[args[0] as usize] == 0 {
+                Flow::Next
+            } else {
+                Flow::Jump
+            }
+        },
+        flipped: None,
+    },
+    // Writes a register to the output
+    OpDefinition {
+        name: "out",
+        operands: &[Operand::Register],
+        flows: &[Flow::Next],
+        execute: |args, registers, output| {
+            output.push(registers[args[0] as usize]);
+            Flow::Next
+        },
+        flipped: None,
+    },
+    // Ends the program
+    OpDefinition {
+        name: "hlt",
+        operands: &[],
+        flows: &[Flow::Halt],
+        execute: |_, _, _| Flow::Halt,
+        flipped: None,
+    },
+];
+
+fn lookup(name: &str) -> Option<&'static OpDefinition> {
+    INSTRUCTION_SET
+        .iter()
+        .find(|definition| definition.name == name)
+}
+
+fn format_operand(operand: Operand, value: i64) -> String {
+    match operand {
+        Operand::Value | Operand::Offset => format!("{:+}", value),
+        Operand::Register => REGISTERS[value as usize].to_string(),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Op {
+    definition: &'static OpDefinition,
+    // Operand values, registers by index. Only as many as the definition has operands are used.
+    values: [i64; MAX_OPERANDS],
+}
+
+impl Op {
+    fn new(definition: &'static OpDefinition, values: &[i64]) -> Op {
+        let mut op = Op {
+            definition,
+            values: [0; MAX_OPERANDS],
+        };
+        op.values[..values.len()].copy_from_slice(values);
+
+        op
+    }
+
+    fn name(&self) -> &'static str {
+        self.definition.name
+    }
+
+    fn arguments(&self) -> &[i64] {
+        &self.values[..self.definition.operands.len()]
+    }
+
+    fn operands(&self) -> Vec<String> {
+        self.definition
+            .operands
+            .iter()
+            .zip(self.arguments())
+            .map(|(&operand, &value)| format_operand(operand, value))
+            .collect()
+    }
+
+    // Absolute targets of the op's offset operands when run at `ip`
+    fn targets(&self, ip: i64) -> Vec<i64> {
+        self.definition
+            .operands
+            .iter()
+            .zip(self.arguments())
+            .filter(|(operand, _)| **operand == Operand::Offset)
+            .map(|(_, offset)| ip + offset)
+            .collect()
+    }
+
+    // Where `flow` leads from this op at `ip` in a program of `len` instructions
+    fn follow(&self, flow: Flow, ip: i64, len: i64) -> i64 {
+        match flow {
+            Flow::Next => ip + 1,
+            Flow::Jump => *self
+                .targets(ip)
+                .first()
+                .unwrap_or_else(|| panic!("{} jumps without an offset operand", self.name())),
+            Flow::Halt => len,
+        }
+    }
+
+    // Everywhere control can go after running this op at `ip`
+    fn successors(&self, ip: i64, len: i64) -> Vec<i64> {
+        self.definition
+            .flows
+            .iter()
+            .map(|&flow| self.follow(flow, ip, len))
+            .collect()
+    }
+
+    fn inverted(&self) -> Option<Op> {
+        self.definition
+            .flipped
+            .and_then(lookup)
+            .map(|definition| Op::new(definition, self.arguments()))
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        for operand in self.operands() {
+            write!(f, " {}", operand)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct TraceStep {
+    ip: i64,
+    op: Op,
+    registers_before: [i64; REGISTERS.len()],
+    accumulator_after: i64,
 }
 
 struct Program {
     instructions: Vec<Op>,
     ip: i64,
-    accumulator: i64,
+    registers: [i64; REGISTERS.len()],
+    // Values written by `out`
+    output: Vec<i64>,
+    // Every step executed since the last reset, if tracing
+    trace: Option<Vec<TraceStep>>,
 }
 
 enum TerminalStatus {
     Loops(i64),
     Terminates(i64),
+    // Jumped before the first instruction
+    OutOfRange(i64),
+    // Still running after `STEP_LIMIT` steps
+    StepLimit(i64),
 }
 
 impl Program {
     fn new(instructions: Vec<Op>) -> Program {
         Program {
-            instructions: instructions,
+            instructions,
             ip: 0,
-            accumulator: 0,
+            registers: [0; REGISTERS.len()],
+            output: Vec::new(),
+            trace: None,
         }
     }
 
+    fn accumulator(&self) -> i64 {
+        self.registers[0]
+    }
+
+    fn enable_trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
     fn step(&mut self) {
-        let current = &self.instructions[self.ip as usize];
-        match current {
-            Op::Nop(_) => {
-                self.ip += 1;
-            }
-            Op::Acc(acc) => {
-                self.accumulator += acc;
-                self.ip += 1;
-            }
-            Op::Jmp(jmp) => {
-                self.ip += jmp;
-            }
+        let ip = self.ip;
+        let registers_before = self.registers;
+        let op = self.instructions[ip as usize];
+
+        let flow = (op.definition.execute)(op.arguments(), &mut self.registers, &mut self.output);
+        self.ip = op.follow(flow, ip, self.instructions.len() as i64);
+
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                ip,
+                op,
+                registers_before,
+                accumulator_after: self.registers[0],
+            });
         }
     }
 
     fn reset(&mut self) {
         self.ip = 0;
-        self.accumulator = 0;
+        self.registers = [0; REGISTERS.len()];
+        self.output.clear();
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
+        }
+    }
+
+    // Registers read by instructions that can go more than one way. Nothing else decides where
+    // control goes, so the program loops once it is back at an instruction with these unchanged.
+    fn control_registers(&self) -> Vec<usize> {
+        let mut registers: Vec<usize> = self
+            .instructions
+            .iter()
+            .filter(|op| op.definition.flows.len() > 1)
+            .flat_map(|op| {
+                op.definition
+                    .operands
+                    .iter()
+                    .zip(op.arguments())
+                    .filter(|(operand, _)| **operand == Operand::Register)
+                    .map(|(_, &register)| register as usize)
+                    .collect::<Vec<usize>>()
+            })
+            .collect();
+        registers.sort_unstable();
+        registers.dedup();
+
+        registers
+    }
+
+    // The instruction pointer and the values of `control` registers
+    fn state(&self, control: &[usize]) -> (i64, Vec<i64>) {
+        (
+            self.ip,
+            control
+                .iter()
+                .map(|&register| self.registers[register])
+                .collect(),
+        )
     }
 
     fn run(&mut self) -> TerminalStatus {
-        let mut instructions_seen = HashSet::<i64>::new();
-        loop {
+        let control = self.control_registers();
+        let mut states_seen = HashSet::<(i64, Vec<i64>)>::new();
+        for _ in 0..STEP_LIMIT {
             if self.ip >= self.instructions.len() as i64 {
-                return TerminalStatus::Terminates(self.accumulator);
+                return TerminalStatus::Terminates(self.accumulator());
+            }
+            if self.ip < 0 {
+                return TerminalStatus::OutOfRange(self.accumulator());
             }
 
-            instructions_seen.insert(self.ip);
+            states_seen.insert(self.state(&control));
             self.step();
-            if instructions_seen.contains(&self.ip) {
-                return TerminalStatus::Loops(self.accumulator);
+            if states_seen.contains(&self.state(&control)) {
+                return TerminalStatus::Loops(self.accumulator());
             }
         }
+
+        TerminalStatus::StepLimit(self.accumulator())
     }
 
     fn invert_op(&mut self, ip: usize) {
-        let new_op = match self.instructions[ip] {
-            Op::Nop(value) => Op::Jmp(value),
-            Op::Acc(value) => Op::Acc(value),
-            Op::Jmp(value) => Op::Nop(value),
+        if let Some(op) = self.instructions[ip].inverted() {
+            self.instructions[ip] = op;
+        }
+    }
+}
+
+// Control flow of a program worked out without running it. Conditional jumps give an instruction
+// more than one successor, so the results cover every path the program could take.
+struct Analysis {
+    successors: Vec<Vec<i64>>,
+    // Whether each instruction can be run when starting from the first
+    reachable: Vec<bool>,
+    // Whether running from each instruction always ends the program by going past the last one
+    terminating: Vec<bool>,
+    // Instructions with a successor before the start of the program or past the instruction just
+    // after the last one. Running still treats a jump past the end as terminating.
+    out_of_range: Vec<usize>,
+    // Each loop once, as a group of instructions that can all reach each other. A plain cycle is
+    // listed in the order it runs, starting from its lowest instruction.
+    loops: Vec<Vec<usize>>,
+}
+
+impl Analysis {
+    fn new(program: &Program) -> Analysis {
+        let len = program.instructions.len();
+        let successors: Vec<Vec<i64>> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(ip, op)| op.successors(ip as i64, len as i64))
+            .collect();
+        let in_program = |ip: i64| 0 <= ip && ip < len as i64;
+
+        let mut reachable = vec![false; len];
+        let mut pending = vec![0];
+        while let Some(ip) = pending.pop() {
+            if in_program(ip) && !reachable[ip as usize] {
+                reachable[ip as usize] = true;
+                pending.extend(&successors[ip as usize]);
+            }
+        }
+
+        // Walk backwards from the end of the program. An instruction terminates once all of its
+        // successors do, so each edge is visited once.
+        let mut predecessors = vec![Vec::<usize>::new(); len];
+        let mut remaining = vec![0; len];
+        let mut terminating = vec![false; len];
+        let mut pending = Vec::<usize>::new();
+        for (ip, nexts) in successors.iter().enumerate() {
+            for &next in nexts {
+                if in_program(next) {
+                    predecessors[next as usize].push(ip);
+                    remaining[ip] += 1;
+                } else if next < 0 {
+                    remaining[ip] += 1;
+                }
+            }
+
+            if remaining[ip] == 0 {
+                terminating[ip] = true;
+                pending.push(ip);
+            }
+        }
+        while let Some(ip) = pending.pop() {
+            for &predecessor in &predecessors[ip] {
+                remaining[predecessor] -= 1;
+                if remaining[predecessor] == 0 {
+                    terminating[predecessor] = true;
+                    pending.push(predecessor);
+                }
+            }
+        }
+
+        let out_of_range = (0..len)
+            .filter(|&ip| {
+                successors[ip]
+                    .iter()
+                    .any(|&next| next < 0 || next > len as i64)
+            })
+            .collect();
+
+        let loops = Analysis::loops(&successors)
+            .into_iter()
+            .map(|group| {
+                let lowest = *group.iter().min().unwrap();
+                let inside = |ip: &&i64| group.contains(&(**ip as usize));
+
+                // Follow the cycle from its lowest instruction if it has no branches
+                let mut body = vec![lowest];
+                while let [next] = successors[*body.last().unwrap()]
+                    .iter()
+                    .filter(inside)
+                    .collect::<Vec<&i64>>()[..]
+                {
+                    if *next as usize == lowest {
+                        return body;
+                    }
+                    body.push(*next as usize);
+                }
+
+                let mut group = group;
+                group.sort_unstable();
+                group
+            })
+            .collect();
+
+        Analysis {
+            successors,
+            reachable,
+            terminating,
+            out_of_range,
+            loops,
+        }
+    }
+
+    // Groups of instructions that can all reach each other and that control can go around, found
+    // with an iterative version of Tarjan's algorithm
+    fn loops(successors: &[Vec<i64>]) -> Vec<Vec<usize>> {
+        let len = successors.len();
+        let mut order = vec![None; len];
+        let mut lowest = vec![0; len];
+        let mut on_stack = vec![false; len];
+        let mut stack = Vec::<usize>::new();
+        let mut visited = 0;
+        let mut loops = Vec::<Vec<usize>>::new();
+
+        for root in 0..len {
+            if order[root].is_some() {
+                continue;
+            }
+
+            let mut calls = vec![(root, 0)];
+            order[root] = Some(visited);
+            lowest[root] = visited;
+            visited += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((ip, position)) = calls.pop() {
+                if let Some(&next) = successors[ip].get(position) {
+                    calls.push((ip, position + 1));
+                    if next < 0 || next >= len as i64 {
+                        continue;
+                    }
+
+                    let next = next as usize;
+                    match order[next] {
+                        None => {
+                            order[next] = Some(visited);
+                            lowest[next] = visited;
+                            visited += 1;
+                            stack.push(next);
+                            on_stack[next] = true;
+                            calls.push((next, 0));
+                        }
+                        Some(seen) if on_stack[next] => lowest[ip] = lowest[ip].min(seen),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                if let Some(&(parent, _)) = calls.last() {
+                    lowest[parent] = lowest[parent].min(lowest[ip]);
+                }
+
+                if Some(lowest[ip]) == order[ip] {
+                    let mut group = Vec::<usize>::new();
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        group.push(member);
+                        if member == ip {
+                            break;
+                        }
+                    }
+
+                    if group.len() > 1 || successors[ip].contains(&(ip as i64)) {
+                        loops.push(group);
+                    }
+                }
+            }
+        }
+
+        loops.sort_unstable_by_key(|group| *group.iter().min().unwrap());
+        loops
+    }
+
+    fn report(&self, program: &Program) -> String {
+        let len = self.successors.len();
+        let ranges = |ips: Vec<usize>| -> String {
+            let mut ranges = Vec::<(usize, usize)>::new();
+            for ip in ips {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end + 1 == ip => *end = ip,
+                    _ => ranges.push((ip, ip)),
+                }
+            }
+
+            if ranges.is_empty() {
+                return "none".to_string();
+            }
+            ranges
+                .iter()
+                .map(|&(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{}-{}", start, end)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
         };
 
-        self.instructions[ip] = new_op;
+        let unreachable = (0..len).filter(|&ip| !self.reachable[ip]).collect();
+        let start = if len == 0 || self.terminating[0] {
+            "Starting from 0 the program terminates".to_string()
+        } else if let Some(&ip) = self
+            .out_of_range
+            .iter()
+            .find(|&&ip| self.reachable[ip] && self.successors[ip].iter().any(|&next| next < 0))
+        {
+            format!(
+                "Starting from 0 the program can jump out of range at {}",
+                ip
+            )
+        } else {
+            "Starting from 0 the program can loop forever".to_string()
+        };
+
+        let mut lines = vec![
+            format!("Instructions: {}", len),
+            start,
+            format!("Unreachable: {}", ranges(unreachable)),
+            format!(
+                "Terminating: {}",
+                ranges((0..len).filter(|&ip| self.terminating[ip]).collect())
+            ),
+        ];
+
+        if self.out_of_range.is_empty() {
+            lines.push("Out of range jumps: none".to_string());
+        } else {
+            lines.push("Out of range jumps:".to_string());
+            lines.extend(self.out_of_range.iter().map(|&ip| {
+                let targets: Vec<String> = self.successors[ip]
+                    .iter()
+                    .filter(|&&next| next < 0 || next > len as i64)
+                    .map(|&next| {
+                        if next < 0 {
+                            next.to_string()
+                        } else {
+                            format!("{} (past the end)", next)
+                        }
+                    })
+                    .collect();
+                format!(
+                    "  {}: {} -> {}",
+                    ip,
+                    program.instructions[ip],
+                    targets.join(", ")
+                )
+            }));
+        }
+
+        if self.loops.is_empty() {
+            lines.push("Loops: none".to_string());
+        } else {
+            lines.push("Loops:".to_string());
+            lines.extend(self.loops.iter().map(|body| {
+                let ips: Vec<String> = body.iter().map(|ip| ip.to_string()).collect();
+                let branches = body.iter().any(|&ip| {
+                    self.successors[ip]
+                        .iter()
+                        .filter(|&&next| next >= 0 && body.contains(&(next as usize)))
+                        .count()
+                        > 1
+                });
+                let reached = if self.reachable[body[0]] {
+                    " (reached from 0)"
+                } else {
+                    ""
+                };
+
+                if branches {
+                    format!("  {} with branches{}", ips.join(", "), reached)
+                } else {
+                    format!("  {} -> {}{}", ips.join(" -> "), body[0], reached)
+                }
+            }));
+        }
+
+        lines.join("\n")
     }
 }
 
-fn parse_arg(arg: &str) -> i64 {
-    let sign = arg.chars().next().unwrap();
-    let value = arg[1..].parse::<i64>().unwrap();
+pub fn analysis_report<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let program = load(input)?;
+
+    Ok(Analysis::new(&program).report(&program))
+}
+
+#[allow(dead_code)]
+pub enum TraceFormat {
+    Text,
+    Csv,
+}
+
+// Runs the boot code recording every step. If it loops, the loop is the trace from the first
+// visit of the state it was about to repeat.
+pub fn trace<I>(input: I, format: TraceFormat) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut program = load(input)?;
+    program.enable_trace();
+
+    let status = program.run();
+    let steps = program.trace.take().unwrap();
+    let control = program.control_registers();
+    let loop_start = match status {
+        TerminalStatus::Loops(_) => steps.iter().position(|step| {
+            step.ip == program.ip
+                && control
+                    .iter()
+                    .all(|&register| step.registers_before[register] == program.registers[register])
+        }),
+        TerminalStatus::Terminates(_)
+        | TerminalStatus::OutOfRange(_)
+        | TerminalStatus::StepLimit(_) => None,
+    };
+    let in_loop = |i: usize| loop_start.is_some_and(|start| i >= start);
+
+    Ok(match format {
+        TraceFormat::Text => {
+            let mut lines: Vec<String> = steps
+                .iter()
+                .enumerate()
+                .map(|(i, step)| {
+                    format!(
+                        "{:>6} {:>5}: {:<10} {} -> {}{}",
+                        i,
+                        step.ip,
+                        step.op.to_string(),
+                        step.registers_before[0],
+                        step.accumulator_after,
+                        if in_loop(i) { "  (loop)" } else { "" }
+                    )
+                })
+                .collect();
+
+            lines.push(match (status, loop_start) {
+                (TerminalStatus::Loops(accumulator), Some(start)) => {
+                    let body: Vec<String> = steps[start..]
+                        .iter()
+                        .map(|step| step.ip.to_string())
+                        .collect();
+                    format!(
+                        "Loops with accumulator {}, entering at {}. Body of {} instructions: {}",
+                        accumulator,
+                        program.ip,
+                        body.len(),
+                        body.join(", ")
+                    )
+                }
+                (TerminalStatus::Loops(accumulator), None) => {
+                    format!("Loops with accumulator {}", accumulator)
+                }
+                (TerminalStatus::Terminates(accumulator), _) => {
+                    format!("Terminates with accumulator {}", accumulator)
+                }
+                (TerminalStatus::OutOfRange(accumulator), _) => format!(
+                    "Jumps out of range to {} with accumulator {}",
+                    program.ip, accumulator
+                ),
+                (TerminalStatus::StepLimit(accumulator), _) => format!(
+                    "Still running after {} steps with accumulator {}",
+                    STEP_LIMIT, accumulator
+                ),
+            });
+
+            if !program.output.is_empty() {
+                let output: Vec<String> = program
+                    .output
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect();
+                lines.push(format!("Output: {}", output.join(", ")));
+            }
 
-    match sign {
-        '+' => value,
-        '-' => -value,
-        _ => panic!("Cannot parse arg: {}", arg),
+            lines.join("\n")
+        }
+        TraceFormat::Csv => {
+            let mut lines = vec![
+                "step,ip,op,argument,accumulator_before,accumulator_after,in_loop".to_string(),
+            ];
+            lines.extend(steps.iter().enumerate().map(|(i, step)| {
+                format!(
+                    "{},{},{},{},{},{},{}",
+                    i,
+                    step.ip,
+                    step.op.name(),
+                    step.op.operands().join(" "),
+                    step.registers_before[0],
+                    step.accumulator_after,
+                    in_loop(i)
+                )
+            }));
+
+            lines.join("\n")
+        }
+    })
+}
+
+struct Repair {
+    ip: usize,
+    from: Op,
+    to: Op,
+    accumulator: i64,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Flipped instruction {} from {} to {}, accumulator {}",
+            self.ip, self.from, self.to, self.accumulator
+        )
+    }
+}
+
+// Flips the first instruction on the original execution path that has a flipped form after which
+// the program always ends, then runs the repaired program. Linear in the length of the program.
+fn repair(program: &mut Program, analysis: &Analysis) -> Option<Repair> {
+    let len = program.instructions.len() as i64;
+    let ends = |ip: i64| ip >= len || (0 <= ip && analysis.terminating[ip as usize]);
+
+    let control = program.control_registers();
+    let mut seen = HashSet::<(i64, Vec<i64>)>::new();
+    program.reset();
+
+    for _ in 0..STEP_LIMIT {
+        let ip = program.ip;
+        if ip < 0 || ip >= len || !seen.insert(program.state(&control)) {
+            break;
+        }
+
+        let from = program.instructions[ip as usize];
+
+        if let Some(to) = from.inverted() {
+            if to.successors(ip, len).into_iter().all(ends) {
+                program.invert_op(ip as usize);
+                program.reset();
+
+                return match program.run() {
+                    TerminalStatus::Terminates(accumulator) => Some(Repair {
+                        ip: ip as usize,
+                        from,
+                        to,
+                        accumulator,
+                    }),
+                    TerminalStatus::Loops(_)
+                    | TerminalStatus::OutOfRange(_)
+                    | TerminalStatus::StepLimit(_) => None,
+                };
+            }
+        }
+
+        program.step();
+    }
+
+    None
+}
+
+pub fn repair_report<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut program = load(input)?;
+
+    let analysis = Analysis::new(&program);
+
+    Ok(match repair(&mut program, &analysis) {
+        Some(repair) => repair.to_string(),
+        None => "No single flipped instruction makes the program terminate".to_string(),
+    })
+}
+
+fn parse_arg(arg: &str) -> Result<i64, String> {
+    let value = arg
+        .get(1..)
+        .and_then(|digits| digits.parse::<i64>().ok())
+        .ok_or_else(|| format!("Cannot parse arg: {}", arg))?;
+
+    match arg.chars().next() {
+        Some('+') => Ok(value),
+        Some('-') => Ok(-value),
+        _ => Err(format!("Cannot parse arg: {}", arg)),
+    }
+}
+
+fn parse_op(text: &str, ip: i64, labels: &HashMap<String, i64>) -> Result<Op, String> {
+    let mut parts = text.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let args: Vec<&str> = parts.collect();
+
+    let definition = lookup(name).ok_or_else(|| format!("Unexpected op: {}", name))?;
+    if args.len() != definition.operands.len() {
+        return Err(format!(
+            "{} takes {} operand(s), found {}",
+            name,
+            definition.operands.len(),
+            args.len()
+        ));
+    }
+
+    let values = definition
+        .operands
+        .iter()
+        .zip(args)
+        .map(|(operand, arg)| match operand {
+            Operand::Value => parse_arg(arg),
+            Operand::Offset if arg.starts_with(['+', '-']) => parse_arg(arg),
+            Operand::Offset => labels
+                .get(arg)
+                .map(|target| target - ip)
+                .ok_or_else(|| format!("Unknown label: {}", arg)),
+            Operand::Register => REGISTERS
+                .iter()
+                .position(|register| arg == register.to_string())
+                .map(|index| index as i64)
+                .ok_or_else(|| format!("Unknown register: {}", arg)),
+        })
+        .collect::<Result<Vec<i64>, String>>()?;
+
+    Ok(Op::new(definition, &values))
+}
+
+// Boot code source is one instruction per line, optionally preceded by "<label>:". A label on a
+// line of its own names the next instruction. Offsets may be given as labels instead of numbers.
+// Anything after ';' is a comment.
+fn assemble<I>(input: I) -> Result<Vec<Op>, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut labels = HashMap::<String, i64>::new();
+    let mut lines = Vec::<(usize, String)>::new();
+
+    for (i, line) in input.enumerate() {
+        let mut text = line.split(';').next().unwrap_or_default().trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            let valid = label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("Line {}: invalid label \"{}\"", i + 1, label));
+            }
+            if labels
+                .insert(label.to_string(), lines.len() as i64)
+                .is_some()
+            {
+                return Err(format!("Line {}: duplicate label {}", i + 1, label));
+            }
+
+            text = rest.trim();
+        }
+
+        if !text.is_empty() {
+            lines.push((i + 1, text.to_string()));
+        }
     }
+
+    lines
+        .iter()
+        .enumerate()
+        .map(|(ip, (line, text))| {
+            parse_op(text, ip as i64, &labels).map_err(|err| format!("Line {}: {}", line, err))
+        })
+        .collect()
+}
+
+fn load<I>(input: I) -> Result<Program, String>
+where
+    I: Iterator<Item = String>,
+{
+    assemble(input)
+        .map(Program::new)
+        .map_err(|err| format!("Invalid boot code: {}", err))
 }
 
-fn parse_op(line: String) -> Op {
-    let mut parts = line.split_whitespace();
-    let op = parts.next().unwrap();
-    let arg = parts.next().unwrap();
+// Assembles `input` into plain boot code, one instruction per line with relative offsets
+pub fn assemble_source<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let ops = assemble(input)?;
+
+    Ok(ops
+        .iter()
+        .map(|op| op.to_string())
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+// Lists the boot code with every jump target labelled and jumps written against those labels.
+// Each line is commented with its index and, for jumps, the original offset. The output can be
+// read back by the assembler.
+pub fn disassemble<I>(input: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let program = load(input)?;
+    let len = program.instructions.len() as i64;
+    let in_range = |target: i64| 0 <= target && target <= len;
+    let label = |target: i64| format!("L{}", target);
+
+    let targets: BTreeSet<i64> = program
+        .instructions
+        .iter()
+        .enumerate()
+        .flat_map(|(ip, op)| op.targets(ip as i64))
+        .filter(|&target| in_range(target))
+        .collect();
 
-    match op {
-        "nop" => Op::Nop(parse_arg(arg)),
-        "acc" => Op::Acc(parse_arg(arg)),
-        "jmp" => Op::Jmp(parse_arg(arg)),
-        _ => panic!("Unexpected op: {}", op),
+    let mut lines: Vec<String> = program
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(ip, op)| {
+            let ip = ip as i64;
+            let mut instruction = vec![op.name().to_string()];
+            instruction.extend(op.definition.operands.iter().zip(op.arguments()).map(
+                |(&operand, &value)| match operand {
+                    Operand::Offset if in_range(ip + value) => label(ip + value),
+                    operand => format_operand(operand, value),
+                },
+            ));
+            let instruction = instruction.join(" ");
+            let comment = if op.targets(ip).is_empty() {
+                ip.to_string()
+            } else {
+                format!("{}: {}", ip, op)
+            };
+            let name = if targets.contains(&ip) {
+                format!("{}:", label(ip))
+            } else {
+                String::new()
+            };
+
+            format!("{:<8}{:<16}; {}", name, instruction, comment)
+        })
+        .collect();
+
+    if targets.contains(&len) {
+        lines.push(format!("{}:", label(len)));
     }
+
+    Ok(lines.join("\n"))
+}
+
+// How many instruction pointers the debugger remembers for backtraces
+const HISTORY_LEN: usize = 1000;
+
+const DEBUGGER_HELP: &str = "\
+break <ip>       stop before executing instruction <ip>
+delete <ip>      remove the breakpoint at <ip>
+step [<count>]   execute <count> instructions (default 1)
+continue         run until a breakpoint, watch, loop, termination or the step limit
+watch            toggle stopping whenever the accumulator changes
+print            show the current instruction and registers
+backtrace [<n>]  show the last <n> instructions executed (default 10)
+reset            restart the program from the beginning
+quit             leave the debugger";
+
+enum StopReason {
+    Breakpoint,
+    Watch(i64),
+    Loop,
+    Terminated,
+    OutOfRange,
+    StepLimit,
+}
+
+struct Debugger {
+    program: Program,
+    breakpoints: HashSet<i64>,
+    watch: bool,
+    history: VecDeque<i64>,
+}
+
+impl Debugger {
+    fn new(program: Program) -> Debugger {
+        Debugger {
+            program,
+            breakpoints: HashSet::new(),
+            watch: false,
+            history: VecDeque::new(),
+        }
+    }
+
+    fn describe(&self, ip: i64) -> String {
+        match self.program.instructions.get(ip as usize) {
+            Some(op) if ip >= 0 => format!("{:>5}: {}", ip, op),
+            _ => format!("{:>5}: <out of range>", ip),
+        }
+    }
+
+    fn current(&self) -> String {
+        let others: String = REGISTERS
+            .iter()
+            .zip(&self.program.registers)
+            .skip(1)
+            .map(|(name, value)| format!(", {} {}", name, value))
+            .collect();
+
+        format!(
+            "{}    accumulator {}{}",
+            self.describe(self.program.ip),
+            self.program.accumulator(),
+            others
+        )
+    }
+
+    // Executes one instruction unless the program has finished or jumped out of range
+    fn step(&mut self) -> Option<StopReason> {
+        let ip = self.program.ip;
+        if ip >= self.program.instructions.len() as i64 {
+            return Some(StopReason::Terminated);
+        }
+        if ip < 0 {
+            return Some(StopReason::OutOfRange);
+        }
+
+        let accumulator = self.program.accumulator();
+        self.program.step();
+
+        self.history.push_back(ip);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+
+        if self.watch && self.program.accumulator() != accumulator {
+            return Some(StopReason::Watch(accumulator));
+        }
+
+        None
+    }
+
+    fn resume(&mut self) -> Option<StopReason> {
+        let control = self.program.control_registers();
+        let mut seen = HashSet::<(i64, Vec<i64>)>::new();
+
+        for _ in 0..STEP_LIMIT {
+            seen.insert(self.program.state(&control));
+            if let Some(reason) = self.step() {
+                return Some(reason);
+            }
+
+            if self.breakpoints.contains(&self.program.ip) {
+                return Some(StopReason::Breakpoint);
+            }
+            if seen.contains(&self.program.state(&control)) {
+                return Some(StopReason::Loop);
+            }
+        }
+
+        Some(StopReason::StepLimit)
+    }
+
+    fn report(&self, reason: Option<StopReason>) -> String {
+        let reason = match reason {
+            None => return self.current(),
+            Some(StopReason::Breakpoint) => "Breakpoint".to_string(),
+            Some(StopReason::Watch(old)) => format!(
+                "Accumulator changed from {} to {}",
+                old,
+                self.program.accumulator()
+            ),
+            Some(StopReason::Loop) => "Instruction about to run a second time".to_string(),
+            Some(StopReason::Terminated) => {
+                return format!("Terminated with accumulator {}", self.program.accumulator())
+            }
+            Some(StopReason::OutOfRange) => "Instruction pointer out of range".to_string(),
+            Some(StopReason::StepLimit) => format!("Still running after {} steps", STEP_LIMIT),
+        };
+
+        format!("{}\n{}", reason, self.current())
+    }
+
+    fn backtrace(&self, count: usize) -> String {
+        let skip = self.history.len().saturating_sub(count);
+        let lines: Vec<String> = self
+            .history
+            .iter()
+            .skip(skip)
+            .map(|&ip| self.describe(ip))
+            .collect();
+
+        if lines.is_empty() {
+            "No instructions executed".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    // Output for one command line, or None to quit
+    fn execute(&mut self, command: &str) -> Option<String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let number = |arg: Option<&&str>, default: Option<i64>| match arg {
+            Some(arg) => arg
+                .parse::<i64>()
+                .map_err(|_| format!("Expected a number, found \"{}\"", arg)),
+            None => default.ok_or_else(|| "Expected a number".to_string()),
+        };
+
+        let output = match words.first() {
+            None => Ok(String::new()),
+            Some(&"break") | Some(&"b") => number(words.get(1), None).map(|ip| {
+                self.breakpoints.insert(ip);
+                format!("Breakpoint at {}", self.describe(ip))
+            }),
+            Some(&"delete") | Some(&"d") => number(words.get(1), None).map(|ip| {
+                if self.breakpoints.remove(&ip) {
+                    format!("Deleted breakpoint at {}", ip)
+                } else {
+                    format!("No breakpoint at {}", ip)
+                }
+            }),
+            Some(&"step") | Some(&"s") => number(words.get(1), Some(1)).map(|count| {
+                let reason = (0..count).find_map(|_| self.step());
+                self.report(reason)
+            }),
+            Some(&"continue") | Some(&"c") => Ok({
+                let reason = self.resume();
+                self.report(reason)
+            }),
+            Some(&"watch") | Some(&"w") => Ok({
+                self.watch = !self.watch;
+                format!(
+                    "Accumulator watch {}",
+                    if self.watch { "on" } else { "off" }
+                )
+            }),
+            Some(&"print") | Some(&"p") => Ok(self.current()),
+            Some(&"backtrace") | Some(&"bt") => {
+                number(words.get(1), Some(10)).map(|count| self.backtrace(count.max(0) as usize))
+            }
+            Some(&"reset") | Some(&"r") => Ok({
+                self.program.reset();
+                self.history.clear();
+                self.current()
+            }),
+            Some(&"quit") | Some(&"q") => return None,
+            Some(&"help") | Some(&"h") => Ok(DEBUGGER_HELP.to_string()),
+            Some(other) => Err(format!("Unknown command \"{}\", try help", other)),
+        };
+
+        Some(output.unwrap_or_else(|err| err))
+    }
+}
+
+// Runs the boot code under a debugger driven by `commands`, one per line. Returns the final
+// accumulator.
+pub fn debug<I, R, W>(input: I, commands: R, out: &mut W) -> Result<i64, String>
+where
+    I: Iterator<Item = String>,
+    R: BufRead,
+    W: Write,
+{
+    let mut debugger = Debugger::new(load(input)?);
+    let mut lines = commands.lines();
+
+    writeln!(out, "{}", debugger.current()).unwrap();
+    loop {
+        write!(out, "(debug) ").unwrap();
+        out.flush().unwrap();
+
+        let output = match lines.next() {
+            Some(line) => debugger.execute(&line.unwrap()),
+            None => None,
+        };
+
+        match output {
+            Some(output) => writeln!(out, "{}", output).unwrap(),
+            None => break,
+        }
+    }
+
+    Ok(debugger.program.accumulator())
 }
 
 impl Day for Day8 {
@@ -107,7 +1254,7 @@ impl Day for Day8 {
     where
         I: Iterator<Item = String>,
     {
-        let mut program = Program::new(input.map(parse_op).collect());
+        let mut program = load(input).unwrap();
         if let TerminalStatus::Loops(result) = program.run() {
             return result;
         }
@@ -120,19 +1267,13 @@ impl Day for Day8 {
     where
         I: Iterator<Item = String>,
     {
-        let mut program = Program::new(input.map(parse_op).collect());
+        let mut program = load(input).unwrap();
 
-        for i in 0..program.instructions.len() {
-            program.invert_op(i);
+        let analysis = Analysis::new(&program);
 
-            if let TerminalStatus::Terminates(result) = program.run() {
-                return result;
-            }
-
-            program.invert_op(i);
-            program.reset();
+        match repair(&mut program, &analysis) {
+            Some(repair) => repair.accumulator,
+            None => panic!("Could not find terminal state"),
         }
-
-        panic!("Could not find terminal state");
     }
 }
@@ -1,77 +1,309 @@
 use crate::day::Day;
 
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::iter::once;
+
 pub struct Day9;
 
+const PREAMBLE: usize = 25;
+
+fn parse<I>(input: I) -> Result<Vec<i64>, String>
+where
+    I: Iterator<Item = String>,
+{
+    input
+        .enumerate()
+        .map(|(i, line)| {
+            line.parse::<i64>()
+                .map_err(|_| format!("Line {}: expected a number, found \"{}\"", i + 1, line))
+        })
+        .collect()
+}
+
+// Yields (index, value, valid) for each number in a stream, where a number is valid if it is the
+// sum of two of the `preamble` numbers before it. Numbers in the preamble itself are valid. The
+// sums of all pairs in the window are kept as a multiset, updated as numbers enter and leave.
+struct Validator<I> {
+    values: I,
+    preamble: usize,
+    index: usize,
+    window: VecDeque<i64>,
+    sums: HashMap<i64, usize>,
+}
+
+fn validate<I>(values: I, preamble: usize) -> Validator<I::IntoIter>
+where
+    I: IntoIterator<Item = i64>,
+{
+    Validator {
+        values: values.into_iter(),
+        preamble,
+        index: 0,
+        window: VecDeque::with_capacity(preamble),
+        sums: HashMap::new(),
+    }
+}
+
+impl<I> Iterator for Validator<I>
+where
+    I: Iterator<Item = i64>,
+{
+    type Item = (usize, i64, bool);
+
+    fn next(&mut self) -> Option<(usize, i64, bool)> {
+        let value = self.values.next()?;
+        let index = self.index;
+        self.index += 1;
+
+        let valid = self.window.len() < self.preamble || self.sums.contains_key(&value);
+
+        if self.window.len() == self.preamble {
+            if let Some(oldest) = self.window.pop_front() {
+                for other in &self.window {
+                    if let Some(count) = self.sums.get_mut(&(oldest + other)) {
+                        *count -= 1;
+                        if *count == 0 {
+                            self.sums.remove(&(oldest + other));
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.preamble > 0 {
+            for other in &self.window {
+                *self.sums.entry(value + other).or_insert(0) += 1;
+            }
+            self.window.push_back(value);
+        }
+
+        Some((index, value, valid))
+    }
+}
+
+// The first number after the preamble that is not the sum of two of the `preamble` numbers
+// before it
+fn first_invalid(values: &[i64], preamble: usize) -> Result<i64, String> {
+    if values.len() < preamble {
+        return Err(format!(
+            "Expected a preamble of {} numbers, found {}",
+            preamble,
+            values.len()
+        ));
+    }
+
+    validate(values.iter().cloned(), preamble)
+        .find(|&(_, _, valid)| !valid)
+        .map(|(_, value, _)| value)
+        .ok_or_else(|| "Ran out of numbers before finding an invalid one".to_string())
+}
+
+// Every number in the stream that fails validation, with its index
+pub fn invalid_numbers<I>(input: I, preamble: usize) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let invalid: Vec<String> = validate(parse(input)?, preamble)
+        .filter(|&(_, _, valid)| !valid)
+        .map(|(index, value, _)| format!("{}: {}", index, value))
+        .collect();
+
+    if invalid.is_empty() {
+        Ok("No invalid numbers".to_string())
+    } else {
+        Ok(invalid.join("\n"))
+    }
+}
+
+struct SumRange {
+    start: usize,
+    // Inclusive
+    end: usize,
+    min: i64,
+    max: i64,
+}
+
+// prefix_sums[i] is the sum of the first i values
+fn prefix_sums(values: &[i64]) -> Vec<i64> {
+    once(0)
+        .chain(values.iter().scan(0, |sum, value| {
+            *sum += value;
+            Some(*sum)
+        }))
+        .collect()
+}
+
+// Smallest and largest value of any range in constant time, from the extremes of every range whose
+// length is a power of two
+struct Extremes {
+    // min[k][i] is the smallest of the 2^k values starting at i, and likewise for max
+    min: Vec<Vec<i64>>,
+    max: Vec<Vec<i64>>,
+}
+
+impl Extremes {
+    fn new(values: &[i64]) -> Extremes {
+        let mut min = vec![values.to_vec()];
+        let mut max = vec![values.to_vec()];
+        let mut width = 1;
+
+        while 2 * width <= values.len() {
+            let combine = |level: &Vec<i64>, pick: fn(i64, i64) -> i64| -> Vec<i64> {
+                (0..=values.len() - 2 * width)
+                    .map(|i| pick(level[i], level[i + width]))
+                    .collect()
+            };
+            min.push(combine(min.last().unwrap(), i64::min));
+            max.push(combine(max.last().unwrap(), i64::max));
+            width *= 2;
+        }
+
+        Extremes { min, max }
+    }
+
+    // Of values[start..=end], which must not be empty
+    fn get(&self, start: usize, end: usize) -> (i64, i64) {
+        let level = (end - start + 1).ilog2() as usize;
+        let last = end + 1 - (1 << level);
+
+        (
+            self.min[level][start].min(self.min[level][last]),
+            self.max[level][start].max(self.max[level][last]),
+        )
+    }
+}
+
+// Every range of at least two contiguous numbers summing to `needle`, ordered by start then end.
+// A range sums to `needle` when the prefix sums at its two ends differ by `needle`, so earlier
+// prefix sums are looked up by value rather than scanned, which also works for negative numbers.
+fn contiguous_ranges(values: &[i64], needle: i64) -> Vec<SumRange> {
+    let prefix_sums = prefix_sums(values);
+    let mut starts = HashMap::<i64, Vec<usize>>::new();
+    let mut bounds = Vec::<(usize, usize)>::new();
+
+    for end in 2..prefix_sums.len() {
+        starts
+            .entry(prefix_sums[end - 2])
+            .or_default()
+            .push(end - 2);
+
+        for &start in starts
+            .get(&(prefix_sums[end] - needle))
+            .into_iter()
+            .flatten()
+        {
+            bounds.push((start, end - 1));
+        }
+    }
+
+    bounds.sort_unstable();
+
+    let extremes = Extremes::new(values);
+    bounds
+        .into_iter()
+        .map(|(start, end)| {
+            let (min, max) = extremes.get(start, end);
+            SumRange {
+                start,
+                end,
+                min,
+                max,
+            }
+        })
+        .collect()
+}
+
+// Start and inclusive end of the range of at least two contiguous numbers summing to `needle` that
+// starts first, ending as early as possible. Only the first index with a given prefix sum can
+// start that range, so one pass is enough.
+fn first_range(values: &[i64], needle: i64) -> Option<(usize, usize)> {
+    let prefix_sums = prefix_sums(values);
+    let mut starts = HashMap::<i64, usize>::new();
+    let mut first = None::<(usize, usize)>;
+
+    for end in 2..prefix_sums.len() {
+        starts.entry(prefix_sums[end - 2]).or_insert(end - 2);
+
+        if let Some(&start) = starts.get(&(prefix_sums[end] - needle)) {
+            if first.is_none_or(|(first_start, _)| start < first_start) {
+                first = Some((start, end - 1));
+            }
+        }
+    }
+
+    first
+}
+
+// Sum of the smallest and largest numbers in the first contiguous range summing to `needle`
+fn weakness(values: &[i64], needle: i64) -> Result<i64, String> {
+    let (start, end) = first_range(values, needle)
+        .ok_or_else(|| format!("No contiguous range sums to {}", needle))?;
+    let range = &values[start..=end];
+
+    Ok(range.iter().min().unwrap() + range.iter().max().unwrap())
+}
+
+pub fn ranges_report<I>(input: I, needle: i64) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let ranges: Vec<String> = contiguous_ranges(&parse(input)?, needle)
+        .iter()
+        .map(|range| {
+            format!(
+                "{}-{} ({} numbers): min {}, max {}, weakness {}",
+                range.start,
+                range.end,
+                range.end - range.start + 1,
+                range.min,
+                range.max,
+                range.min + range.max
+            )
+        })
+        .collect();
+
+    if ranges.is_empty() {
+        Ok(format!("No contiguous range sums to {}", needle))
+    } else {
+        Ok(ranges.join("\n"))
+    }
+}
+
+// Both answers for a stream using a preamble of the given length
+pub fn break_encryption<I>(input: I, preamble: usize) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let values = parse(input)?;
+    let invalid = first_invalid(&values, preamble)?;
+    let weakness = weakness(&values, invalid)?;
+
+    Ok(format!(
+        "First invalid number: {}\nEncryption weakness: {}",
+        invalid, weakness
+    ))
+}
+
 impl Day for Day9 {
     type T1 = i64;
     fn part1<I>(input: I) -> i64
     where
         I: Iterator<Item = String>,
     {
-        let mut window: [i64; 25] = [0; 25];
-        let window_size = window.len();
-        let mut index = 0;
-        let mut values = input.map(|line| line.parse::<i64>().unwrap());
-
-        // Prelude
-        for i in 0..window_size {
-            window[i] = values.next().unwrap();
-        }
+        let values = parse(input).unwrap();
 
-        loop {
-            let next_value = values.next().unwrap();
-
-            let found = window
-                .iter()
-                .enumerate()
-                .map(|(i, x)| window.iter().skip(i + 1).map(move |y| x + y))
-                .flatten()
-                .any(|sum| sum == next_value);
-
-            if found {
-                window[index] = next_value;
-                index += 1;
-                index %= window_size;
-            } else {
-                return next_value;
-            }
-        }
+        first_invalid(&values, PREAMBLE).unwrap()
     }
 
-    type T2 = u64;
-    fn part2<I>(input: I) -> u64
+    type T2 = i64;
+    fn part2<I>(input: I) -> i64
     where
         I: Iterator<Item = String>,
     {
-        let needle = 70639851;
-        let values: Vec<u64> = input.map(|line| line.parse::<u64>().unwrap()).collect();
-        let mut begin = 0;
-        let mut end = 0;
-        let mut sum = values[begin];
-
-        while sum != needle {
-            if sum < needle {
-                end += 1;
-                sum += values[end];
-            } else {
-                sum -= values[begin];
-                begin += 1;
-            }
-        }
+        let values = parse(input).unwrap();
+        let needle = first_invalid(&values, PREAMBLE).unwrap();
 
-        let min = values
-            .iter()
-            .skip(begin)
-            .take(end - begin + 1)
-            .min()
-            .unwrap();
-        let max = values
-            .iter()
-            .skip(begin)
-            .take(end - begin + 1)
-            .max()
-            .unwrap();
-
-        return min + max;
+        weakness(&values, needle).unwrap()
     }
 }
@@ -2,6 +2,8 @@ mod days;
 mod util;
 
 use days::*;
+use std::io;
+use std::ops::Range;
 use util::file;
 
 #[allow(dead_code)]
@@ -68,6 +70,125 @@ fn run(day: Day, part: Part) -> String {
     }
 }
 
+#[allow(dead_code)]
+enum Tool {
+    // Rows of the map to draw; all of them if None
+    TobogganPath(Option<Range<usize>>),
+    PassportReport,
+    PassportExport(day4::ExportFormat),
+    DecodeBoardingPass(day5::Plane, String),
+    EncodeBoardingPass(day5::Plane, u64),
+    SeatMap,
+    SeatGaps,
+    CustomsAnswers(day6::Aggregate),
+    // Bag given as "<descriptor> <color>"
+    BagQuery(day7::Query, String),
+    BagGraph(day7::DotScope),
+    // Reads commands from stdin
+    BootDebugger,
+    BootRepair,
+    BootTrace(day8::TraceFormat),
+    BootDisassemble,
+    BootAnalysis,
+    // Source file name under input/
+    BootAssemble(String),
+    // Preamble length
+    XmasWeakness(usize),
+    // Preamble length
+    XmasInvalid(usize),
+    // Target sum
+    XmasRanges(i64),
+    JoltageHistogram(day10::Tolerance),
+    // Lists at most the given number of arrangements
+    JoltageArrangements(day10::Tolerance, usize),
+    JoltageCount(day10::Tolerance, day10::Precision),
+    // Occupied seats once the layout settles
+    Seating(day11::Rules),
+    // Terminal frames go to stdout
+    SeatingAnimation(day11::Rules, day11::Animation),
+}
+
+fn run_tool(tool: Tool) -> String {
+    match tool {
+        Tool::TobogganPath(rows) => day3::render(file::input(3), day3::slopes(), rows),
+        Tool::PassportReport => day4::Schema::load()
+            .map_or_else(|err| err, |schema| day4::report(file::input(4), &schema)),
+        Tool::PassportExport(format) => day4::Schema::load().map_or_else(
+            |err| err,
+            |schema| day4::export(file::input(4), &schema, format),
+        ),
+        Tool::DecodeBoardingPass(plane, pass) => plane
+            .decode(&pass)
+            .map_or_else(|err| err, |seat_id| seat_id.to_string()),
+        Tool::EncodeBoardingPass(plane, seat_id) => plane.encode(seat_id).unwrap_or_else(|err| err),
+        Tool::SeatMap => day5::seat_map(file::input(5)).unwrap_or_else(|err| err),
+        Tool::SeatGaps => day5::gaps(file::input(5)).unwrap_or_else(|err| err),
+        Tool::CustomsAnswers(aggregate) => day6::count(file::input(6), aggregate).to_string(),
+        Tool::BagQuery(query, bag) => {
+            day7::query(file::input(7), query, &bag).unwrap_or_else(|err| err)
+        }
+        Tool::BagGraph(scope) => day7::to_dot(file::input(7), scope).unwrap_or_else(|err| err),
+        Tool::BootDebugger => day8::debug(file::input(8), io::stdin().lock(), &mut io::stdout())
+            .map_or_else(
+                |err| err,
+                |accumulator| format!("Accumulator: {}", accumulator),
+            ),
+        Tool::BootRepair => day8::repair_report(file::input(8)).unwrap_or_else(|err| err),
+        Tool::BootTrace(format) => day8::trace(file::input(8), format).unwrap_or_else(|err| err),
+        Tool::BootDisassemble => day8::disassemble(file::input(8)).unwrap_or_else(|err| err),
+        Tool::BootAnalysis => day8::analysis_report(file::input(8)).unwrap_or_else(|err| err),
+        Tool::BootAssemble(name) => match file::config(&name) {
+            Some(source) => day8::assemble_source(source).unwrap_or_else(|err| err),
+            None => format!("Cannot open input/{}", name),
+        },
+        Tool::XmasWeakness(preamble) => {
+            day9::break_encryption(file::input(9), preamble).unwrap_or_else(|err| err)
+        }
+        Tool::XmasInvalid(preamble) => {
+            day9::invalid_numbers(file::input(9), preamble).unwrap_or_else(|err| err)
+        }
+        Tool::XmasRanges(needle) => {
+            day9::ranges_report(file::input(9), needle).unwrap_or_else(|err| err)
+        }
+        Tool::JoltageHistogram(tolerance) => day10::histogram(file::input(10), tolerance),
+        Tool::JoltageCount(tolerance, precision) => {
+            day10::count(file::input(10), tolerance, precision).unwrap_or_else(|err| err)
+        }
+        Tool::Seating(rules) => day11::run(file::input(11), &rules)
+            .map_or_else(|err| err, |occupied| occupied.to_string()),
+        Tool::SeatingAnimation(rules, animation) => {
+            day11::animate(file::input(11), &rules, &animation, &mut io::stdout())
+                .unwrap_or_else(|err| err)
+        }
+        Tool::JoltageArrangements(tolerance, limit) => {
+            day10::arrangements(file::input(10), tolerance)
+                .take(limit)
+                .map(|arrangement| {
+                    arrangement
+                        .iter()
+                        .map(|joltage| joltage.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+    }
+}
+
+#[allow(dead_code)]
+enum Task {
+    Solve(Day, Part),
+    Tool(Tool),
+}
+
 fn main() {
-    println!("{}", run(Day::Nineteen, Part::Two));
+    let task = Task::Solve(Day::Nineteen, Part::Two);
+
+    let output = match task {
+        Task::Solve(day, part) => run(day, part),
+        Task::Tool(tool) => run_tool(tool),
+    };
+
+    println!("{}", output);
 }
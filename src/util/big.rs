@@ -0,0 +1,114 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::AddAssign;
+
+const BASE: u64 = 1_000_000_000;
+
+// Unsigned integer of any size. Only what counting needs: addition, conversion and printing.
+#[derive(Clone, Default)]
+pub struct BigUint {
+    // Base 10^9 digits, least significant first, with no trailing zeros (so zero is empty)
+    digits: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn to_u64(&self) -> Option<u64> {
+        self.digits.iter().rev().try_fold(0u64, |value, &digit| {
+            value.checked_mul(BASE)?.checked_add(digit as u64)
+        })
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut value: u64) -> BigUint {
+        let mut digits = Vec::<u32>::new();
+        while value > 0 {
+            digits.push((value % BASE) as u32);
+            value /= BASE;
+        }
+
+        BigUint { digits }
+    }
+}
+
+impl AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        if self.digits.len() < other.digits.len() {
+            self.digits.resize(other.digits.len(), 0);
+        }
+
+        let mut carry = 0;
+        for (i, digit) in self.digits.iter_mut().enumerate() {
+            let sum = *digit as u64 + other.digits.get(i).cloned().unwrap_or(0) as u64 + carry;
+            *digit = (sum % BASE) as u32;
+            carry = sum / BASE;
+        }
+
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+}
+
+impl<'a> Sum<&'a BigUint> for BigUint {
+    fn sum<I>(iter: I) -> BigUint
+    where
+        I: Iterator<Item = &'a BigUint>,
+    {
+        iter.fold(BigUint::default(), |mut sum, value| {
+            sum += value;
+            sum
+        })
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.digits.split_last() {
+            None => write!(f, "0"),
+            Some((most_significant, rest)) => {
+                write!(f, "{}", most_significant)?;
+                for digit in rest.iter().rev() {
+                    write!(f, "{:09}", digit)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigUint;
+
+    fn sum(values: &[u64]) -> BigUint {
+        values
+            .iter()
+            .map(|&value| BigUint::from(value))
+            .collect::<Vec<BigUint>>()
+            .iter()
+            .sum()
+    }
+
+    #[test]
+    fn carries_across_digits() {
+        assert_eq!(sum(&[999_999_999, 1]).to_string(), "1000000000");
+        assert_eq!(
+            sum(&[999_999_999_999_999_999, 1]).to_string(),
+            "1000000000000000000"
+        );
+        assert_eq!(sum(&[1_000_000_000, 5]).to_string(), "1000000005");
+        assert_eq!(sum(&[]).to_string(), "0");
+    }
+
+    #[test]
+    fn converts_to_u64_until_it_overflows() {
+        assert_eq!(sum(&[999_999_999, 1]).to_u64(), Some(1_000_000_000));
+        assert_eq!(BigUint::from(u64::MAX).to_u64(), Some(u64::MAX));
+
+        let past_max = sum(&[u64::MAX, 1]);
+        assert_eq!(past_max.to_string(), "18446744073709551616");
+        assert_eq!(past_max.to_u64(), None);
+    }
+}
@@ -5,3 +5,9 @@ pub fn input(day: u8) -> impl Iterator<Item = String> {
     let file = File::open(format!("input/Day{}.txt", day)).unwrap();
     io::BufReader::new(file).lines().map(|line| line.unwrap())
 }
+
+// Optional extra file alongside the puzzle inputs, e.g. a rule set overriding built-in defaults
+pub fn config(name: &str) -> Option<impl Iterator<Item = String>> {
+    let file = File::open(format!("input/{}", name)).ok()?;
+    Some(io::BufReader::new(file).lines().map(|line| line.unwrap()))
+}
@@ -1 +1,2 @@
+pub mod big;
 pub mod file;